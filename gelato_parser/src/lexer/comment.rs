@@ -0,0 +1,83 @@
+use std::fmt::Display;
+
+use crate::lexer::{Lexer, Parse, span::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    /// `-- comment` or `// comment`, until the end of the line.
+    Line,
+    /// `/* comment */`, which can be nested.
+    Block,
+}
+/// Comments aren't tokens, they are kept on the side as trivia
+/// so tools like formatters can put them back.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub span: Span,
+    pub kind: CommentKind,
+    pub text: String,
+    /// False for a block comment that the text ends inside of, which takes up the rest of it.
+    pub terminated: bool,
+}
+impl Display for Comment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+impl Parse for Comment {
+    fn parse(tokenizer: &mut Lexer) -> Option<Self> {
+        let start = tokenizer.cursor;
+        let mut terminated = true;
+        let kind = if tokenizer.is_next("--") || tokenizer.is_next("//") {
+            while tokenizer.cursor < tokenizer.buf.len() && tokenizer.get_char() != '\n' {
+                tokenizer.cursor += 1;
+            }
+            CommentKind::Line
+        } else if tokenizer.is_next("/*") {
+            let mut depth = 0;
+            terminated = false;
+            while tokenizer.cursor < tokenizer.buf.len() {
+                if tokenizer.is_next("/*") {
+                    depth += 1;
+                    tokenizer.cursor += 2;
+                } else if tokenizer.is_next("*/") {
+                    depth -= 1;
+                    tokenizer.cursor += 2;
+                    if depth == 0 {
+                        terminated = true;
+                        break;
+                    }
+                } else {
+                    tokenizer.cursor += 1;
+                }
+            }
+            CommentKind::Block
+        } else {
+            return None;
+        };
+        let end = tokenizer.cursor;
+        Some(Self { span: Span::new(start..end), kind, text: tokenizer.buf[start..end].to_string(), terminated })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn nested_block_comment_is_terminated() {
+        let mut lexer = Lexer::new("a /* b /* c */ d */ e".to_string());
+        let tokens = lexer.parse();
+        assert_eq!(tokens.tokens.len(), 2);
+        assert!(lexer.trivia[0].terminated);
+    }
+
+    #[test]
+    fn unterminated_block_comment_takes_the_rest() {
+        let mut lexer = Lexer::new("a /* b /* c */ d".to_string());
+        let tokens = lexer.parse();
+        assert_eq!(tokens.tokens.len(), 1);
+        assert!(!lexer.trivia[0].terminated);
+        assert_eq!(lexer.trivia[0].span.range, 2..16);
+    }
+}
@@ -39,8 +39,13 @@ impl Parse for Delimiter {
         };
         while let Some(token) = tokenizer.parse_token() {
             tokenizer.skip_whitespace();
-            let get = tokenizer.get_char();
             del.tokens.tokens.push(token);
+            // Unclosed groups end with the text, and keep '\0' as their close
+            if tokenizer.cursor == tokenizer.buf.len() {
+                del.span.range = start..tokenizer.cursor;
+                return Some(del);
+            }
+            let get = tokenizer.get_char();
             match open {
                 '(' => if get == ')' { 
                     del.close = get;
@@ -61,7 +66,10 @@ impl Parse for Delimiter {
                 _ => {}
             }
         }
-        tokenizer.cursor += 1;
+        if tokenizer.cursor < tokenizer.buf.len() {
+            del.close = tokenizer.get_char();
+            tokenizer.cursor += 1;
+        }
         let end = tokenizer.cursor;
         del.span.range = start..end;
         Some(del)
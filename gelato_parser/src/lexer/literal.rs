@@ -29,6 +29,17 @@ impl Parse for Literal {
     fn parse(tokenizer: &mut Lexer) -> Option<Self> {
         let start = tokenizer.cursor;
 
+        // A string goes on to its closing quote, so what it holds isn't read as a comment
+        if tokenizer.is_next("\"") {
+            let rest = &tokenizer.buf[start + 1..];
+            let end = rest.find('"').map_or(tokenizer.buf.len(), |i| start + i + 2);
+            tokenizer.cursor = end;
+            return Some(Literal {
+                span: Span::new(start..end),
+                kind: LitKind::String,
+                value: tokenizer.buf[start..end].to_string(),
+            });
+        }
         let mut literal = String::new();
         while tokenizer.is_next_literal() {
             literal.push(tokenizer.next_char());
@@ -46,4 +57,30 @@ impl Parse for Literal {
             value: literal,
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, literal::LitKind, tokens::Token};
+
+    #[test]
+    fn string_holds_comment_markers() {
+        let mut lexer = Lexer::new("include \"a--b//c/*d.lam\" x".to_string());
+        let tokens = lexer.parse();
+        assert!(lexer.trivia.is_empty());
+        let Token::Literal(literal) = &tokens.tokens[1] else {
+            panic!("expected a literal, got {:?}", tokens.tokens[1]);
+        };
+        assert_eq!(literal.kind, LitKind::String);
+        assert_eq!(literal.value, "\"a--b//c/*d.lam\"");
+        assert_eq!(tokens.tokens.len(), 3);
+    }
+
+    #[test]
+    fn unterminated_string_takes_the_rest() {
+        let mut lexer = Lexer::new("\"a -- b".to_string());
+        let tokens = lexer.parse();
+        assert_eq!(tokens.tokens.len(), 1);
+        assert!(lexer.trivia.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::lexer::tokens::{Token, Tokens};
+use crate::lexer::{comment::Comment, tokens::{Token, Tokens}};
 
 pub mod tokens;
 pub mod literal;
@@ -6,6 +6,7 @@ pub mod delimiter;
 pub mod ident;
 pub mod punct;
 pub mod span;
+pub mod comment;
 
 pub trait Parse: Sized {
     fn parse(lexer: &mut Lexer) -> Option<Self>;
@@ -14,11 +15,19 @@ pub trait Parse: Sized {
 pub struct Lexer {
     buf: String,
     cursor: usize,
+    /// The comments that were skipped, in the order they appeared.
+    pub trivia: Vec<Comment>,
+    /// Punctuation that is longer than one character, like `::` or `<=`.
+    operators: Vec<String>,
 }
 
 impl Lexer {
     pub fn new(buf: String) -> Self {
-        Self { buf, cursor: 0usize }
+        Self { buf, cursor: 0usize, trivia: vec![], operators: vec![] }
+    }
+    /// A lexer that reads each of the operators as a single punct, instead of one per character.
+    pub fn with_operators(buf: String, operators: Vec<String>) -> Self {
+        Self { buf, cursor: 0usize, trivia: vec![], operators }
     }
     pub fn parse(&mut self) -> Tokens {
         let mut tokens = vec![];
@@ -27,20 +36,34 @@ impl Lexer {
         }
         Tokens { tokens: tokens, next: 0 }
     }
+    /// Skips whitespace and comments, the comments are stored in the trivia.
     pub fn skip_whitespace(&mut self) {
-        for c in self.buf.as_bytes()[self.cursor..self.buf.len()].iter().cloned() {
-            if c != b' ' && c != b'\t' && c != b'\n' {
-                break;
+        loop {
+            for c in self.buf.as_bytes()[self.cursor..self.buf.len()].iter().cloned() {
+                if c != b' ' && c != b'\t' && c != b'\n' && c != b'\r' {
+                    break;
+                }
+                self.cursor += 1;
+            }
+            match Comment::parse(self) {
+                Some(comment) => self.trivia.push(comment),
+                None => return,
             }
-            self.cursor += 1;
         }
     }
+    pub fn is_next(&self, str: &str) -> bool {
+        self.buf[self.cursor..].starts_with(str)
+    }
     pub fn is_next_punct(&self) -> bool {
         if self.cursor == self.buf.len() {
             return false;
         }
+        // Declared operators win over groups, so `<=` isn't the start of a `<...>`
+        if self.operators.iter().any(|operator| self.is_next(operator)) {
+            return true;
+        }
         let c = self.buf.as_bytes()[self.cursor];
-        "~`!@#$%^&*-=+|;:',./\\?".contains(c as char)
+        "~`!@#$%^&*-=+|;:',./\\?>".contains(c as char)
     }
     pub fn is_next_literal(&self) -> bool {
         if self.cursor == self.buf.len() {
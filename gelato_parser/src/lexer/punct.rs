@@ -28,11 +28,37 @@ impl Parse for Punct {
         let start = tokenizer.cursor;
 
         let mut punct = String::new();
-        if tokenizer.is_next_punct() {
+        // Take the longest operator that matches, otherwise a single character
+        let operator = tokenizer.operators.iter()
+            .filter(|operator| tokenizer.is_next(operator))
+            .max_by_key(|operator| operator.len());
+        if let Some(operator) = operator {
+            punct.push_str(operator);
+            tokenizer.cursor += operator.len();
+        } else if tokenizer.is_next_punct() {
             punct.push(tokenizer.next_char());
         }
 
         let end = tokenizer.cursor;
         Some(Self { punct, span: Span::new(start..end) })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, tokens::Token};
+
+    #[test]
+    fn declared_operator_starting_with_angle_bracket() {
+        let mut lexer = Lexer::with_operators("a <= b".to_string(), vec!["<=".to_string()]);
+        let tokens = lexer.parse();
+        assert_eq!(tokens.tokens.len(), 3);
+        assert!(matches!(&tokens.tokens[1], Token::Punct(punct) if punct.punct == "<=" && punct.span.range == (2..4)));
+    }
+
+    #[test]
+    fn undeclared_angle_bracket_is_a_group() {
+        let mut lexer = Lexer::new("<a>".to_string());
+        let tokens = lexer.parse();
+        assert!(matches!(&tokens.tokens[..], [Token::Group(group)] if group.open == '<' && group.close == '>'));
+    }
+}
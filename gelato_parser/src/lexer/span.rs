@@ -8,4 +8,8 @@ impl Span {
     pub fn new(range: Range<usize>) -> Self {
         Self { range }
     }
+    /// The smallest span that covers both spans.
+    pub fn join(&self, other: &Span) -> Span {
+        Span::new(self.range.start.min(other.range.start)..self.range.end.max(other.range.end))
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use std::{fmt::{Display, Write}, io::{Cursor, Read}};
+use std::fmt::Display;
 
 use crate::lexer::{Lexer, Parse, delimiter::Delimiter, ident::Ident, literal::Literal, punct::Punct, span::Span};
 
@@ -0,0 +1,148 @@
+use std::fmt::Display;
+
+use crate::{app::{App, CHECK_STEPS}, ast::Statements, error::ASTError, expansion::SourceMap};
+
+/// A directive that checks how terms evaluate.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Assertion {
+    /// `#assert_eq a b`, both terms have the same normal form.
+    Equal(String),
+    /// `#assert_normalizes e within steps`, the term reaches a normal form within that many steps.
+    /// The steps are checked along with the term, so a typo fails like any other assertion.
+    Normalizes(String, Option<String>),
+}
+
+impl Assertion {
+    /// Parses `#assert_eq ...` and `#assert_normalizes ...`.
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(terms) = text.strip_prefix("#assert_eq ") {
+            return Some(Assertion::Equal(terms.trim().to_string()));
+        }
+        let term = text.strip_prefix("#assert_normalizes ")?;
+        match term.rsplit_once(" within ") {
+            Some((term, steps)) => Some(Assertion::Normalizes(term.trim().to_string(), Some(steps.trim().to_string()))),
+            None => Some(Assertion::Normalizes(term.trim().to_string(), None)),
+        }
+    }
+}
+
+/// How many assertions passed and failed, and how many entries couldn't run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Results {
+    pub passed: usize,
+    pub failed: usize,
+    pub errors: usize,
+}
+
+impl App {
+    /// Prints an error of something that couldn't be loaded or defined,
+    /// which fails the test run like an assertion that failed.
+    pub fn error(&mut self, msg: impl Display) {
+        self.results.errors += 1;
+        println!("{msg}");
+    }
+    /// Checks the assertion and reports the result along with where it was written.
+    pub fn assert(&mut self, assertion: &Assertion) {
+        let (text, result) = match assertion {
+            Assertion::Equal(terms) => (format!("#assert_eq {terms}"), self.assert_equal(terms)),
+            Assertion::Normalizes(term, Some(steps)) => (format!("#assert_normalizes {term} within {steps}"), self.assert_normalizes(term, steps)),
+            Assertion::Normalizes(term, None) => (format!("#assert_normalizes {term}"), self.normalize(term, CHECK_STEPS).map(|_| ())),
+        };
+        let location = self.location();
+        match result {
+            Ok(()) => {
+                self.results.passed += 1;
+                println!("passed {location}{text}");
+            }
+            Err(msg) => {
+                self.results.failed += 1;
+                println!("FAILED {location}{text}\n{msg}");
+            }
+        }
+    }
+    fn assert_equal(&mut self, terms: &str) -> Result<(), String> {
+        // The two terms are the two statements, so applications need parenthesis
+        let statements = self.calculator.parse(terms).map_err(|err| self.report(&err, terms, &SourceMap::default()))?;
+        let spans: Vec<_> = statements.statements.iter().map(|statement| statement.span().range.clone()).collect();
+        let [left, right] = spans.as_slice() else {
+            return Err(format!("  Expected two terms, but there are {}, applications have to be in parenthesis", spans.len()));
+        };
+        let left = self.normalize(&terms[left.clone()], CHECK_STEPS)?;
+        let right = self.normalize(&terms[right.clone()], CHECK_STEPS)?;
+        if left.alpha_equivalent(&right) {
+            return Ok(());
+        }
+        let left = self.print_evaluated(left);
+        let right = self.print_evaluated(right);
+        Err(diff(&left, &right))
+    }
+    fn assert_normalizes(&self, term: &str, steps: &str) -> Result<(), String> {
+        let steps = steps.parse().map_err(|_| format!("  '{steps}' isn't a number of steps"))?;
+        self.normalize(term, steps).map(|_| ())
+    }
+    /// The normal form of the term, and an error that says why it doesn't have one.
+    fn normalize(&self, term: &str, steps: usize) -> Result<Statements, String> {
+        match self.normal_form(term, steps) {
+            Ok(Some(statements)) => Ok(statements),
+            Ok(None) => Err(format!("  {term} doesn't reach a normal form within {steps} steps")),
+            Err(ASTError::EmptyTokenList) => Err("  Expected a term".to_string()),
+            Err(err) => Err(self.report(&err, term, &SourceMap::default())),
+        }
+    }
+    /// Loads the files and prints how many of their assertions passed.
+    /// Returns false if any of them failed, if none were found, or if
+    /// a file or one of its entries couldn't be loaded.
+    pub fn test(&mut self, paths: &[String]) -> bool {
+        for path in paths {
+            self.load_file(path);
+        }
+        let Results { passed, failed, errors } = self.results;
+        match errors {
+            0 => println!("{passed} passed, {failed} failed"),
+            1 => println!("{passed} passed, {failed} failed, 1 error"),
+            _ => println!("{passed} passed, {failed} failed, {errors} errors"),
+        }
+        failed == 0 && errors == 0 && passed > 0
+    }
+}
+
+/// Both normal forms, with the first character they differ at marked.
+fn diff(left: &str, right: &str) -> String {
+    let column = left.chars().zip(right.chars()).take_while(|(left, right)| left == right).count();
+    format!("  - {left}\n  + {right}\n    {}^", " ".repeat(column))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::files::test_directory;
+
+    use super::*;
+
+    /// Writes the file into the directory of the test and gives its path.
+    fn write(test: &str, text: &str) -> String {
+        let path = test_directory(test).join("test.lam");
+        std::fs::write(&path, text).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn passing_file_is_green() {
+        let ok = write("passing_file_is_green", "#id = \\x. x\n#assert_eq (#id y) y");
+        assert!(App::new().test(&[ok]));
+    }
+
+    #[test]
+    fn unreadable_file_fails_the_run() {
+        let ok = write("unreadable_file_fails_the_run", "#id = \\x. x\n#assert_eq (#id y) y");
+        let missing = ok.replace("test.lam", "missing.lam");
+        assert!(!App::new().test(&[missing, ok]));
+    }
+
+    #[test]
+    fn definition_error_fails_the_run() {
+        let broken = write("definition_error_fails_the_run", "#bad = (\\x. x\n#assert_eq y y");
+        let mut app = App::new();
+        assert!(!app.test(&[broken]));
+        assert_eq!(app.results, Results { passed: 1, failed: 0, errors: 1 });
+    }
+}
@@ -1,34 +1,62 @@
 use std::collections::LinkedList;
 
-use gelato_parser::lexer::Lexer;
+use gelato_parser::lexer::{Lexer, span::Span};
 
-use crate::{app::state::{CalculatorState, ControlFlow}, ast::Statements, error::ASTError};
+use crate::{app::state::{CalculatorState, ControlFlow, Strategy}, ast::{FromTokens, Statements}, data::DataType, error::ASTError, expansion::SourceMap, operators::Operators, types::Type};
 pub struct Calculator {
-    lexer: Lexer,
     pub state: Option<CalculatorState>,
+    pub strategy: Strategy,
+    pub operators: Operators,
 }
 
 impl Calculator {
     pub fn empty() -> Self {
-        let lexer = Lexer::new(String::new());
         let state = None;
-        Self { lexer, state }
+        Self { state, strategy: Strategy::default(), operators: Operators::default() }
     }
-    pub fn new(text: &str) -> Result<Self, ASTError> {
-        let mut lexer = Lexer::new(text.to_string());
-        let state = Some(CalculatorState::new(&mut lexer)?);
-        Ok(Self { lexer, state })
+    /// Starts evaluating the statements, which have their macros expanded.
+    pub fn input(&mut self, statements: Statements) {
+        self.state = Some(CalculatorState::new(statements, self.strategy));
     }
-    pub fn input(&mut self, text: &str) -> Result<(), ASTError> {
-        self.lexer = Lexer::new(text.to_string());
-        self.state = Some(CalculatorState::new(&mut self.lexer)?);
-        Ok(())
+    /// Parses the text with the declared operators, without running it.
+    pub fn parse(&self, text: &str) -> Result<Statements, ASTError> {
+        let mut lexer = Lexer::with_operators(text.to_string(), self.operators.symbols());
+        let mut tokens = lexer.parse();
+        check_comments(&lexer)?;
+        Statements::from_tokens(&mut tokens, &self.operators)
+    }
+    /// Parses the text as a type, all of it has to be part of the type.
+    pub fn parse_type(&self, text: &str) -> Result<Type, ASTError> {
+        let mut lexer = Lexer::with_operators(text.to_string(), self.operators.symbols());
+        let mut tokens = lexer.parse();
+        check_comments(&lexer)?;
+        let ty = Type::from_tokens(&mut tokens, &self.operators).map_err(|err| match err {
+            ASTError::EmptyTokenList => ASTError::Syntax("Expected a type".to_string(), Span::new(0..text.len())),
+            err => err,
+        })?;
+        match tokens.tokens.get(tokens.next) {
+            Some(token) => Err(ASTError::Syntax("Expected the end of the type".to_string(), token.span().clone())),
+            None => Ok(ty),
+        }
     }
     pub fn statements(&self) -> Statements {
         if let Some(state) = &self.state {
             state.statements.clone()
         } else {
-            Statements { statements: LinkedList::new() }
+            Statements { statements: LinkedList::new(), span: Span::new(0..0) }
+        }
+    }
+    /// Replaces the cases with the case analysis of their data types.
+    pub fn desugar_cases(&mut self, data: &[DataType], map: &SourceMap, module: &str) -> Result<(), ASTError> {
+        match self.state.as_mut() {
+            Some(state) => state.statements.desugar_cases(data, map, module),
+            None => Ok(()),
+        }
+    }
+    /// Removes the types from the statements before they run.
+    pub fn erase_types(&mut self) {
+        if let Some(state) = self.state.as_mut() {
+            state.statements.erase_types();
         }
     }
     pub fn step(&mut self) -> ControlFlow {
@@ -38,4 +66,32 @@ impl Calculator {
             ControlFlow::Finished
         }
     }
-}
\ No newline at end of file
+}
+/// Block comments that the text ends inside of are an error at their '/*', like unclosed parenthesis.
+pub fn check_comments(lexer: &Lexer) -> Result<(), ASTError> {
+    match lexer.trivia.iter().find(|comment| !comment.terminated) {
+        Some(comment) => {
+            let start = comment.span.range.start;
+            Err(ASTError::Syntax("Unterminated block comment".to_string(), Span::new(start..start + 2)))
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_block_comment_is_an_error() {
+        let calculator = Calculator::empty();
+        match calculator.parse("\\x. x /* a /* b */") {
+            Err(ASTError::Syntax(msg, span)) => {
+                assert_eq!(msg, "Unterminated block comment");
+                assert_eq!(span.range, 6..8);
+            }
+            other => panic!("expected a syntax error, got {other:?}"),
+        }
+        assert!(calculator.parse("\\x. x /* a /* b */ c */ y").is_ok());
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use gelato_parser::lexer::Lexer;
+
+use crate::{app::{App, calculator::check_comments}, error::ASTError, expansion::SourceMap};
+
+/// A `.lam` file that is being loaded.
+pub struct SourceFile {
+    /// The path it was loaded with, relative to the file that included it.
+    pub path: PathBuf,
+    /// Tells files apart, since the same file can be reached through several paths.
+    pub canonical: PathBuf,
+    /// The line the entry that runs starts on, from 1.
+    pub line: usize,
+}
+
+/// Splits the text of a file into its entries, along with the line each one starts on.
+/// An entry is a line and the indented lines after it. Comments are blanked out
+/// first, so block comments can go over several lines, and one that never ends is an error.
+pub fn entries(text: &str) -> Result<Vec<(usize, String)>, ASTError> {
+    let mut lexer = Lexer::new(text.to_string());
+    lexer.parse();
+    check_comments(&lexer)?;
+    let mut text = text.to_string();
+    // From the last comment, so the spans of the others stay where they are
+    for comment in lexer.trivia.iter().rev() {
+        let blank: String = comment.text.chars().map(|c| if c == '\n' { '\n' } else { ' ' }).collect();
+        text.replace_range(comment.span.range.clone(), &blank);
+    }
+    let mut entries: Vec<(usize, String)> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match entries.last_mut() {
+            Some((_, entry)) if line.starts_with([' ', '\t']) => {
+                entry.push('\n');
+                entry.push_str(line);
+            }
+            _ => entries.push((i + 1, line.to_string())),
+        }
+    }
+    Ok(entries)
+}
+
+/// An empty directory for the files of one test, named after the test and the process,
+/// so tests that run at the same time, or in another checkout, don't share files.
+#[cfg(test)]
+pub fn test_directory(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("lambda_calculus_{}_{test}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+impl App {
+    /// Runs every entry of a `.lam` file like a line of the REPL. The path is relative
+    /// to the file that is being loaded, if there is one, which is how `include` works.
+    pub fn load_file(&mut self, path: &str) {
+        let path = self.resolve(path);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                self.error(format!("{}Can't read {}: {err}", self.location(), path.display()));
+                return;
+            }
+        };
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(i) = self.files.iter().position(|file| file.canonical == canonical) {
+            let cycle: Vec<String> = self.files[i..].iter()
+                .map(|file| file.path.display().to_string())
+                .chain([path.display().to_string()])
+                .collect();
+            self.error(format!("{}{} includes itself, {}", self.location(), path.display(), cycle.join(" -> ")));
+            return;
+        }
+        self.files.push(SourceFile { path, canonical, line: 1 });
+        let entries = match entries(&text) {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.error(self.report(&err, &text, &SourceMap::default()));
+                self.files.pop();
+                return;
+            }
+        };
+        for (line, entry) in entries {
+            if let Some(file) = self.files.last_mut() {
+                file.line = line;
+            }
+            // exit() only stops the file
+            if !self.execute(&entry) {
+                break;
+            }
+        }
+        self.files.pop();
+    }
+    /// The path relative to the file that is being loaded, or to the working directory.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        match self.files.last().and_then(|file| file.path.parent()) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+    /// Where the entry that runs was written, like `lib/bool.lam:3: `, and nothing in the REPL.
+    pub fn location(&self) -> String {
+        match self.files.last() {
+            Some(file) => format!("{}:{}: ", file.path.display(), file.line),
+            None => String::new(),
+        }
+    }
+    /// Formats the error like `ASTError::report`, with the file and the line it's on when a file is being loaded.
+    pub fn report(&self, err: &ASTError, source: &str, map: &SourceMap) -> String {
+        let report = err.report(source, map);
+        let Some(file) = self.files.last() else {
+            return report;
+        };
+        // Entries can go over several lines
+        let lines = err.span()
+            .and_then(|span| source.get(..map.origin(span).span.range.start.min(source.len())))
+            .map_or(0, |before| before.matches('\n').count());
+        format!("{}:{}: {report}", file.path.display(), file.line + lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_markers_in_a_path_are_kept() {
+        let entries = entries("include \"lib/a--b.lam\" -- the file\ninclude \"x//y/*z.lam\"").unwrap();
+        assert_eq!(entries, [(1, "include \"lib/a--b.lam\"            ".to_string()), (2, "include \"x//y/*z.lam\"".to_string())]);
+    }
+
+    #[test]
+    fn includes_a_path_with_dashes() {
+        let directory = test_directory("includes_a_path_with_dashes");
+        std::fs::write(directory.join("a--b.lam"), "#one = \\f x. f x").unwrap();
+        std::fs::write(directory.join("main.lam"), "include \"a--b.lam\"").unwrap();
+        let mut app = App::new();
+        app.load_file(&directory.join("main.lam").display().to_string());
+        assert!(app.macros.contains_key("#one"));
+        assert_eq!(app.results.errors, 0);
+    }
+}
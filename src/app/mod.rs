@@ -1,53 +1,678 @@
-use std::{collections::HashMap, process::Command};
+use std::{collections::{BTreeMap, HashMap, HashSet}, process::Command};
 
-use gelato_parser::lexer::{Lexer, tokens::{Token, Tokens}};
+use gelato_parser::lexer::{Lexer, span::Span, tokens::{Token, Tokens}};
 
-use crate::{app::calculator::Calculator, ast::{FromTokens, Statements}, error::ASTError};
+use crate::{ast::{Statement, Statements, fresh_name}, data::{Constructor, DataType, Encoding, Field, is_name, split_fields}, app::{assertions::{Assertion, Results}, calculator::{Calculator, check_comments}, files::SourceFile, modules::Namespace, state::CalculatorState, recursion::{compile_recursive, identifiers, macro_references, recursive_groups, replace_macro_reference}, state::Strategy}, error::ASTError, expansion::{Definition, SourceMap, is_macro_name, macro_name, module_of}, operators::{Associativity, COMMENTS, Operator, Operators, RESERVED}, prelude::{self, PRELUDE}, printer::PrintOptions, readback::read_back, types::{Mode, Type, inference::{Inference, Scheme}, simple::SimpleChecker, system_f::SystemFChecker, constructions::ConstructionsChecker, usage::Usage, synthesis::{MAX_SIZE, synthesize}}};
 
 pub mod state;
 pub mod calculator;
+pub mod recursion;
+pub mod files;
+pub mod modules;
+pub mod assertions;
+/// How many terms `synthesize()` prints at most.
+const SYNTHESIZED_TERMS: usize = 5;
+/// How many steps the examples of the prelude get to reach their normal form.
+const CHECK_STEPS: usize = 10_000;
+/// How many steps a macro gets to reach the normal form that gets folded into its name.
+const FOLD_STEPS: usize = 1000;
+/// The name, parameters and body of a macro definition.
+type MacroDefinition = (String, Vec<String>, String);
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Events {
     Nothing,
-    Error,
     Exit,
     Clear,
-    CreateMacro(String, String)
+    SetStrategy(Strategy),
+    SetPrinter(PrintOptions),
+    SetMode(Mode),
+    SetUsage(Usage),
+    QueryType(String),
+    Synthesize(String),
+    Error(String),
+    SetEncoding(Encoding),
+    CreateOperator(Associativity, u8, String, String),
+    CreateData(DataType),
+    /// The name, parameters and body of a macro, and whether it's private.
+    CreateMacro(String, Vec<String>, String, bool),
+    ListMacros,
+    ShowMacro(String),
+    DeleteMacro(String),
+    RenameMacro(String, String),
+    ListPrelude,
+    LoadPrelude(String),
+    CheckPrelude,
+    LoadFile(String),
+    Import(String, Option<String>),
+    Use(String, Option<String>),
+    Assert(Assertion),
 }
 
 pub struct App {
     calculator: Calculator,
-    macros: HashMap<String, String>
+    printer: PrintOptions,
+    mode: Mode,
+    /// How many times bound variables may be used, checked along with the types.
+    usage: Usage,
+    /// The encoding data types declared from now on get.
+    encoding: Encoding,
+    /// The data types that have been declared.
+    data: Vec<DataType>,
+    macros: HashMap<String, Definition>,
+    /// The type schemes of the macros that have a type.
+    types: HashMap<String, Scheme>,
+    /// The macros that can only be used by the macros of their own module.
+    private: HashSet<String>,
+    /// The files that are being loaded, the innermost last.
+    files: Vec<SourceFile>,
+    /// The module that is being imported.
+    namespace: Option<Namespace>,
+    /// The operators each imported module declared, which only
+    /// the module itself and the files that `use` it can use.
+    module_operators: BTreeMap<String, Operators>,
+    /// The names of the macros in the order they were first defined.
+    order: Vec<String>,
+    /// The normal forms of the macros that can be folded into their names,
+    /// found when they're first needed after the macros change.
+    normal_forms: Option<Vec<(String, Statements)>>,
+    /// How many of the assertions that ran passed and failed.
+    results: Results,
 }
 
 impl App {
     pub fn new() -> Self {
-        Self { 
+        Self {
             calculator: Calculator::empty(),
+            printer: PrintOptions::default(),
+            mode: Mode::default(),
+            usage: Usage::default(),
+            encoding: Encoding::default(),
+            data: Vec::new(),
             macros: HashMap::new(),
+            types: HashMap::new(),
+            private: HashSet::new(),
+            files: Vec::new(),
+            namespace: None,
+            module_operators: BTreeMap::new(),
+            order: Vec::new(),
+            normal_forms: None,
+            results: Results::default(),
+        }
+    }
+    /// Defines a macro, or replaces its definition. The macros that use it see
+    /// the new definition from now on, and their types are inferred again.
+    pub fn insert_macro(&mut self, key: &str, parameters: Vec<String>, value: &str) {
+        let definition = match self.define(value, value, parameters) {
+            Ok(definition) => definition,
+            Err(err) => {
+                self.error(format!("{key} isn't defined\n{}", self.report(&err, value, &SourceMap::default())));
+                return;
+            }
+        };
+        let before = self.group_of(key);
+        if !self.order.iter().any(|name| name == key) {
+            self.order.push(key.to_string());
+        }
+        let previous = self.macros.insert(key.to_string(), definition);
+        if let Err(msg) = self.check_group(key) {
+            self.error(msg);
+            match previous {
+                Some(previous) => self.macros.insert(key.to_string(), previous),
+                None => self.macros.remove(key),
+            };
+            return;
+        }
+        let undefined: Vec<&String> = self.macros[key].references.iter()
+            .filter(|reference| !self.macros.contains_key(*reference))
+            .collect();
+        if !undefined.is_empty() {
+            let undefined: Vec<&str> = undefined.iter().map(|name| name.as_str()).collect();
+            println!("{key} uses {}, which has to be defined before {key} can be used", undefined.join(", "));
+        }
+        self.update(key, before);
+    }
+    /// The graph of the macros, every macro with the macros it uses.
+    fn references(&self) -> BTreeMap<String, Vec<String>> {
+        self.macros.iter().map(|(name, definition)| (name.clone(), definition.references.clone())).collect()
+    }
+    /// The macros that use the macro directly.
+    fn users_of(&self, name: &str) -> Vec<String> {
+        let mut users: Vec<String> = self.macros.iter()
+            .filter(|(_, definition)| definition.references.iter().any(|reference| reference == name))
+            .map(|(user, _)| user.clone())
+            .collect();
+        users.sort();
+        users
+    }
+    /// The macros that are recursive together with the macro, which is only the macro itself
+    /// if it isn't recursive, and nothing if it isn't defined.
+    fn group_of(&self, name: &str) -> Vec<String> {
+        recursive_groups(&self.references()).into_iter()
+            .find(|group| group.iter().any(|member| member == name))
+            .unwrap_or_default()
+    }
+    /// The macro that a macro is defined as, for definitions like `#a = #b`.
+    fn alias_of(&self, name: &str) -> Option<String> {
+        let mut statements = self.macros.get(name)?.body.as_ref()?;
+        loop {
+            match statements.statements.iter().collect::<Vec<_>>().as_slice() {
+                [Statement::Group(group)] => statements = &group.statements,
+                [Statement::Macro(reference)] if reference.arguments.is_empty() => return Some(reference.name.clone()),
+                _ => return None,
+            }
+        }
+    }
+    /// Checks that the group of the macro can be compiled with a fixed point combinator.
+    fn check_group(&self, key: &str) -> Result<(), String> {
+        let group = self.group_of(key);
+        if group.len() == 1 && !self.macros[key].references.iter().any(|reference| reference == key) {
+            return Ok(());
+        }
+        // Every use would expand into another use, without ever ending
+        if let Some(name) = group.iter().find(|name| !self.macros[*name].parameters.is_empty()) {
+            return Err(format!("{name} has parameters, so it can't be recursive"));
+        }
+        if let Some(name) = group.iter().find(|name| self.macros[*name].body.is_none()) {
+            return Err(format!("{name} is a type, so it can't be recursive"));
+        }
+        // Macros that are only each other don't have a term to take the fixed point of
+        let mut cycle = vec![key.to_string()];
+        while let Some(next) = self.alias_of(cycle.last().unwrap()) {
+            let done = next == key || cycle.contains(&next);
+            cycle.push(next);
+            if done {
+                break;
+            }
+        }
+        if cycle.len() > 1 && cycle.last().is_some_and(|last| last == key) {
+            return Err(format!("{} is a cycle that never ends", cycle.join(" = ")));
+        }
+        Ok(())
+    }
+    /// Compiles the groups that changed along with `key` again, which are the group it was
+    /// in `before` and the groups that use it, and infers their types again.
+    fn update(&mut self, key: &str, before: Vec<String>) {
+        self.normal_forms = None;
+        let mut affected: HashSet<String> = before.into_iter().collect();
+        affected.insert(key.to_string());
+        loop {
+            let users: Vec<String> = self.macros.iter()
+                .filter(|(name, definition)| !affected.contains(*name) && definition.references.iter().any(|reference| affected.contains(reference)))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if users.is_empty() {
+                break;
+            }
+            affected.extend(users);
+        }
+        // The groups come after the groups they use, which have their types by then
+        for group in recursive_groups(&self.references()) {
+            if group.iter().any(|name| affected.contains(name)) {
+                let report = group.iter().any(|name| name == key);
+                self.compile_group(&group, report);
+            }
+        }
+    }
+    /// Switches the strategy, and compiles every macro again so the recursive
+    /// ones get the fixed point combinator of the new strategy.
+    fn set_strategy(&mut self, strategy: Strategy) {
+        self.calculator.strategy = strategy;
+        self.normal_forms = None;
+        for group in recursive_groups(&self.references()) {
+            self.compile_group(&group, false);
         }
     }
-    pub fn insert_macro(&mut self, key: &str, value: &str) {
-        let mut value = value.to_string();
-        self.apply_macros(&mut value);
-        self.macros.insert(key.to_string(), value.to_string());
+    /// Compiles the group with the operators of its module, which the group
+    /// can use even where they aren't declared.
+    fn compile_group(&mut self, group: &[String], report: bool) {
+        let Some(operators) = self.module_operators.get(module_of(&group[0])).cloned() else {
+            return self.compile_members(group, report);
+        };
+        let outer = self.calculator.operators.clone();
+        for operator in operators.operators {
+            self.calculator.operators.insert(operator);
+        }
+        self.compile_members(group, report);
+        self.calculator.operators = outer;
     }
-    // returns true if it created a valid macro
-    pub fn parse_macro(&self, tokens: &mut Tokens) -> Option<(String, String)> {
-        let punct = tokens.next()?;
-        let ident = if punct.is_punct_subset("#") { // # will be our macro symbol
-            let ident = tokens.next()?;
-            ident.get_ident()?
+    /// Parses the bodies of a group from their sources, compiling them with the fixed point
+    /// combinator of the strategy if they are recursive, and infers their types.
+    /// Errors in the types are only printed when `report` is set.
+    fn compile_members(&mut self, group: &[String], report: bool) {
+        let definitions: Vec<(String, String)> = group.iter().map(|name| (name.clone(), self.macros[name].source.clone())).collect();
+        let (name, _) = &definitions[0];
+        let recursive = definitions.len() > 1 || self.macros[name].references.contains(name);
+        let compiled = if recursive {
+            compile_recursive(&definitions, self.calculator.strategy)
         } else {
+            definitions.clone()
+        };
+        for ((name, source), (_, body)) in definitions.iter().zip(compiled) {
+            let parameters = self.macros[name].parameters.clone();
+            match self.define(source, body.trim(), parameters) {
+                Ok(mut definition) => {
+                    // The fixed point combinator isn't a type
+                    if recursive {
+                        definition.ty = None;
+                    }
+                    self.macros.insert(name.clone(), definition);
+                }
+                Err(err) => self.error(format!("{name} couldn't be compiled\n{}", self.report(&err, body.trim(), &SourceMap::default()))),
+            }
+        }
+        // The body of a macro with parameters is checked where the macro gets expanded
+        if group.iter().any(|name| !self.macros[name].parameters.is_empty()) {
+            for name in group {
+                self.types.remove(name);
+            }
+            return;
+        }
+        // The types are inferred from the definitions before they are compiled,
+        // because the fixed point combinators don't have a type
+        match self.infer_definitions(&definitions) {
+            Ok(schemes) => {
+                for ((name, _), scheme) in definitions.iter().zip(schemes) {
+                    self.types.insert(name.clone(), scheme);
+                }
+            }
+            Err(err) => {
+                // Macros that use macros which aren't defined yet don't have a type yet either
+                let defined = definitions.iter()
+                    .all(|(name, _)| self.macros[name].references.iter().all(|reference| self.macros.contains_key(reference)));
+                for (name, _) in definitions.iter() {
+                    self.types.remove(name);
+                    if report && defined && self.mode == Mode::Inferred {
+                        println!("{name} has no type, {err}");
+                    }
+                }
+            }
+        }
+    }
+    /// Parses the body of a macro from `text`, which is the `source` it was defined with or what
+    /// that compiles to. Bodies that aren't terms can still be types, which are used inside of types.
+    /// The macros in the body are only expanded where the macro gets used.
+    fn define(&self, source: &str, text: &str, parameters: Vec<String>) -> Result<Definition, ASTError> {
+        let span = Span::new(0..text.len());
+        let ty = self.calculator.parse_type(text).ok();
+        let body = match self.calculator.parse(text) {
+            Ok(body) if !body.statements.is_empty() => Some(body),
+            Err(_) if ty.is_some() => None,
+            Ok(_) | Err(ASTError::EmptyTokenList) => return Err(ASTError::Syntax("Expected a term".to_string(), span)),
+            Err(err) => return Err(err),
+        };
+        let references = macro_references(source);
+        Ok(Definition { source: source.to_string(), parameters, references, body, ty, len: text.len() })
+    }
+    /// Parses the text and replaces the macros in it with their definitions.
+    /// The returned map traces the expansions back to the text that was written.
+    pub fn parse_expanded(&self, text: &str, map: &mut SourceMap) -> Result<Statements, ASTError> {
+        let mut statements = self.calculator.parse(text)?;
+        statements.expand_macros(&self.macros, map, text.len())?;
+        Ok(statements)
+    }
+    /// Prints a term that was evaluated, with the normal forms of the macros
+    /// folded into their names if the printer is set to.
+    fn print_evaluated(&mut self, mut statements: Statements) -> String {
+        if self.printer.fold {
+            statements.fold_macros(self.normal_forms());
+        }
+        self.printer.statements(&statements)
+    }
+    /// The normal forms of the macros that are closed abstractions, which are the ones that can stand
+    /// for parts of other terms. When macros share a normal form, the ones that aren't aliases
+    /// come first, and then the ones that were defined first.
+    fn normal_forms(&mut self) -> &[(String, Statements)] {
+        if self.normal_forms.is_none() {
+            let mut names: Vec<&String> = self.order.iter()
+                .filter(|name| self.macros.get(*name).is_some_and(|definition| definition.parameters.is_empty() && definition.body.is_some()))
+                .filter(|name| !self.private.contains(*name))
+                .collect();
+            // Sorting keeps the order of the definitions that are both aliases or both not
+            names.sort_by_key(|name| self.alias_of(name).is_some());
+            let forms = names.into_iter()
+                .filter_map(|name| Some((name.clone(), self.normal_form(name, FOLD_STEPS).ok()??)))
+                .filter(|(_, form)| form.is_abstraction() && form.free_variables().is_empty())
+                .collect();
+            self.normal_forms = Some(forms);
+        }
+        self.normal_forms.as_deref().unwrap_or_default()
+    }
+    /// Prints every macro with the text it was defined with.
+    pub fn list_macros(&self) {
+        let mut names: Vec<&String> = self.macros.keys().collect();
+        names.sort();
+        for name in names {
+            let definition = &self.macros[name];
+            let private = if self.private.contains(name) { "private " } else { "" };
+            println!("{private}{} = {}", definition.head(name), definition.source);
+        }
+    }
+    /// Prints the text a macro was defined with, what it expands to, and the macros that use it.
+    pub fn show_macro(&self, name: &str) {
+        let Some(definition) = self.macros.get(name) else {
+            println!("'{name}' is not defined");
+            return;
+        };
+        let head = definition.head(name);
+        if let Some(entry) = prelude::entry(name).filter(|entry| entry.body == definition.source) {
+            println!("-- {}", entry.doc);
+        }
+        println!("{head} = {}", definition.source);
+        match (&definition.body, &definition.ty) {
+            (Some(body), _) => {
+                let mut body = body.clone();
+                match body.expand_macros(&self.macros, &mut SourceMap::default(), definition.len) {
+                    Ok(()) => println!("{head} = {}", self.printer.statements(&body)),
+                    Err(err) => println!("{err}"),
+                }
+            }
+            (None, Some(ty)) => match ty.expand_macros(&self.macros) {
+                Ok(ty) => println!("{head} = {ty}"),
+                Err(msg) => println!("{msg}"),
+            },
+            (None, None) => {}
+        }
+        if let Some(scheme) = self.types.get(name) {
+            println!("{name} : {scheme}");
+        }
+        let users = self.users_of(name);
+        if !users.is_empty() {
+            println!("{name} is used by {}", users.join(", "));
+        }
+    }
+    /// Forgets a macro, the macros that use it can't be used until it's defined again.
+    pub fn delete_macro(&mut self, name: &str) {
+        let before = self.group_of(name);
+        if self.macros.remove(name).is_none() {
+            println!("'{name}' is not defined");
+            return;
+        }
+        self.types.remove(name);
+        self.private.remove(name);
+        self.order.retain(|defined| defined != name);
+        let users = self.users_of(name);
+        if !users.is_empty() {
+            println!("{name} is still used by {}", users.join(", "));
+        }
+        self.update(name, before);
+    }
+    /// Gives a macro another name, the macros that use it use the new name.
+    pub fn rename_macro(&mut self, from: &str, to: &str) {
+        if !is_macro_name(to) {
+            println!("'{to}' can't be used as the name of a macro");
+            return;
+        }
+        if self.macros.contains_key(to) {
+            println!("'{to}' is already defined");
+            return;
+        }
+        if !self.macros.contains_key(from) {
+            println!("'{from}' is not defined");
+            return;
+        }
+        // The users are defined again first, so nothing changes if one of them can't be
+        let mut redefined = vec![];
+        for user in self.users_of(from) {
+            let definition = &self.macros[&user];
+            let source = replace_macro_reference(&definition.source, from, to);
+            match self.define(&source, &source, definition.parameters.clone()) {
+                // The macro itself is one of the users if it's recursive
+                Ok(definition) => redefined.push((if user == from { to.to_string() } else { user }, definition)),
+                Err(err) => {
+                    self.error(format!("'{from}' isn't renamed, {user} can't be defined with '{to}'\n{}", self.report(&err, &source, &SourceMap::default())));
+                    return;
+                }
+            }
+        }
+        let before = self.group_of(from);
+        let definition = self.macros.remove(from).unwrap();
+        self.macros.insert(to.to_string(), definition);
+        self.types.remove(from);
+        if self.private.remove(from) {
+            self.private.insert(to.to_string());
+        }
+        for name in self.order.iter_mut().filter(|name| *name == from) {
+            *name = to.to_string();
+        }
+        let modules = self.module_operators.values_mut();
+        for operator in std::iter::once(&mut self.calculator.operators).chain(modules).flat_map(|operators| operators.operators.iter_mut()) {
+            operator.term.rename_macro(from, to);
+        }
+        for (user, definition) in redefined {
+            self.macros.insert(user, definition);
+        }
+        self.update(to, before);
+    }
+    /// Prints the modules of the prelude and the macros they define.
+    pub fn list_prelude() {
+        for module in PRELUDE {
+            let names: Vec<&str> = module.entries.iter().map(|entry| entry.name).collect();
+            println!("{}: {}, {}", module.name, module.doc, names.join(", "));
+        }
+    }
+    /// Defines the macros of a module of the prelude, after the modules it uses,
+    /// or of every module for `all`. Macros with the same names get replaced.
+    pub fn load_prelude(&mut self, name: &str) {
+        let modules: Vec<&str> = PRELUDE.iter().map(|module| module.name).collect();
+        if name != "all" && !modules.contains(&name) {
+            println!("'{name}' isn't part of the prelude, which has {} and all", modules.join(", "));
+            return;
+        }
+        let mut loaded = vec![];
+        for module in modules.into_iter().filter(|module| name == "all" || *module == name) {
+            self.load_module(module, &mut loaded);
+        }
+    }
+    /// Defines the macros of the module and of the modules it uses that aren't `loaded` yet.
+    fn load_module(&mut self, name: &'static str, loaded: &mut Vec<&'static str>) {
+        let Some(module) = prelude::module(name).filter(|_| !loaded.contains(&name)) else {
+            return;
+        };
+        loaded.push(name);
+        for required in module.requires {
+            self.load_module(required, loaded);
+        }
+        for entry in module.entries {
+            self.insert_macro(entry.name, vec![], entry.body);
+        }
+        let names: Vec<&str> = module.entries.iter().map(|entry| entry.name).collect();
+        println!("{}: {}", module.name, names.join(", "));
+    }
+    /// Runs the examples of the prelude and prints the ones that don't have the normal forms they should.
+    pub fn check_prelude() {
+        let (failures, total) = Self::check_examples();
+        for failure in failures.iter() {
+            println!("{failure}");
+        }
+        println!("{} of {total} checks passed", total - failures.len());
+    }
+    /// Runs the examples of the prelude and compares their normal forms with the ones they should have,
+    /// returning what went wrong with the ones that don't and how many examples there are. They run
+    /// with every module loaded, so the macros that were defined don't get replaced.
+    pub fn check_examples() -> (Vec<String>, usize) {
+        let mut app = App::new();
+        for entry in PRELUDE.iter().flat_map(|module| module.entries) {
+            app.insert_macro(entry.name, vec![], entry.body);
+        }
+        let (mut failures, mut total) = (vec![], 0);
+        for entry in PRELUDE.iter().flat_map(|module| module.entries) {
+            for (example, expected) in entry.examples {
+                total += 1;
+                let (result, wanted) = match (app.normal_form(example, CHECK_STEPS), app.normal_form(expected, CHECK_STEPS)) {
+                    (Ok(result), Ok(wanted)) => (result, wanted),
+                    (Err(err), _) | (_, Err(err)) => {
+                        failures.push(format!("{}: {err}", entry.name));
+                        continue;
+                    }
+                };
+                match (result, wanted) {
+                    (Some(result), Some(wanted)) if result.alpha_equivalent(&wanted) => {}
+                    (Some(result), Some(wanted)) => {
+                        failures.push(format!("{}: {example} should be {}, but is {}", entry.name, app.printer.statements(&wanted), app.printer.statements(&result)));
+                    }
+                    _ => failures.push(format!("{}: {example} doesn't reach a normal form within {CHECK_STEPS} steps", entry.name)),
+                }
+            }
+        }
+        (failures, total)
+    }
+    /// Evaluates the text in normal order, None if it doesn't reach a normal form within `limit` steps.
+    pub fn normal_form(&self, text: &str, limit: usize) -> Result<Option<Statements>, ASTError> {
+        let mut map = SourceMap::default();
+        let mut statements = self.parse_expanded(text, &mut map)?;
+        statements.desugar_cases(&self.data, &map, self.current_module())?;
+        statements.erase_types();
+        Ok(CalculatorState::normalize(statements, Strategy::NormalOrder, limit))
+    }
+    /// Parses `infixl 6 + = #add` into its associativity, precedence, symbol and term.
+    pub fn parse_operator(&self, text: &str) -> Option<Result<(Associativity, u8, String, String), String>> {
+        let mut words = text.split_whitespace();
+        let associativity = match words.next()? {
+            "infixl" => Associativity::Left,
+            "infixr" => Associativity::Right,
+            "infix" => Associativity::None,
+            _ => return None,
+        };
+        let Some(precedence) = words.next().and_then(|precedence| precedence.parse().ok()) else {
+            return Some(Err("Expected a precedence from 0 to 255".to_string()));
+        };
+        let Some(symbol) = words.next() else {
+            return Some(Err("Expected an operator".to_string()));
+        };
+        if !symbol.chars().all(|c| "~`!@$%^&*-=+|;:',./?<>".contains(c))
+            || RESERVED.contains(&symbol)
+            || COMMENTS.iter().any(|comment| symbol.starts_with(comment)) {
+            return Some(Err(format!("'{symbol}' can't be used as an operator")));
+        }
+        if words.next() != Some("=") {
+            return Some(Err("Expected '=' after the operator".to_string()));
+        }
+        let Some(start) = text.find(" = ").map(|i| i + 3) else {
+            return Some(Err("Expected '=' after the operator".to_string()));
+        };
+        Some(Ok((associativity, precedence, symbol.to_string(), text[start..].trim().to_string())))
+    }
+    /// Declares an operator, its term is parsed with the operators known so far.
+    /// Like in the body of a macro, the macros in it are expanded where it's used.
+    pub fn insert_operator(&mut self, associativity: Associativity, precedence: u8, symbol: &str, term: &str) {
+        match self.calculator.parse(term) {
+            Ok(statements) if statements.statements.is_empty() => {
+                self.error(format!("'{symbol}' needs a term"));
+            }
+            Ok(statements) => {
+                let undefined: Vec<String> = macro_references(term).into_iter()
+                    .filter(|reference| !self.macros.contains_key(reference))
+                    .collect();
+                if !undefined.is_empty() {
+                    println!("'{symbol}' uses {}, which has to be defined before '{symbol}' can be used", undefined.join(", "));
+                }
+                let symbol = symbol.to_string();
+                self.declare_operator(Operator { symbol, associativity, precedence, term: statements });
+            }
+            Err(err) => self.error(self.report(&err, term, &SourceMap::default())),
+        }
+    }
+    /// Makes the operator usable, and a part of the module that is being imported if there is one.
+    fn declare_operator(&mut self, operator: Operator) {
+        if let Some(namespace) = &self.namespace {
+            self.module_operators.entry(namespace.name.clone()).or_default().insert(operator.clone());
+        }
+        self.calculator.operators.insert(operator);
+    }
+    /// Parses `data List a = Nil | Cons a (List a)`, the data type gets the current encoding.
+    pub fn parse_data(&self, text: &str) -> Option<Result<DataType, String>> {
+        let rest = text.strip_prefix("data ")?;
+        let Some((head, body)) = rest.split_once('=') else {
+            return Some(Err("Expected '=' after the name of the data type".to_string()));
+        };
+        let mut words = head.split_whitespace();
+        let Some(name) = words.next() else {
+            return Some(Err("Expected the name of the data type".to_string()));
+        };
+        let parameters: Vec<String> = words.map(str::to_string).collect();
+        if let Some(word) = std::iter::once(name).chain(parameters.iter().map(String::as_str)).find(|word| !is_name(word)) {
+            return Some(Err(format!("'{word}' can't be used as a name")));
+        }
+        let mut constructors: Vec<Constructor> = vec![];
+        for alternative in body.split('|') {
+            let fields = match split_fields(alternative) {
+                Ok(fields) => fields,
+                Err(msg) => return Some(Err(msg)),
+            };
+            let mut fields = fields.into_iter();
+            let Some(constructor) = fields.next() else {
+                return Some(Err("Expected a constructor".to_string()));
+            };
+            if !is_name(&constructor) {
+                return Some(Err(format!("'{constructor}' can't be used as a constructor")));
+            }
+            if constructors.iter().any(|declared| declared.name == constructor) {
+                return Some(Err(format!("'{constructor}' is declared twice")));
+            }
+            let fields = fields.map(|ty| {
+                // `List` and `(List a)` both hold another list
+                let head = ty.split(|c: char| c == '(' || c == ')' || c.is_whitespace()).find(|word| !word.is_empty());
+                Field { recursive: head == Some(name), ty }
+            }).collect();
+            constructors.push(Constructor { name: constructor, fields });
+        }
+        Some(Ok(DataType { name: name.to_string(), parameters, constructors, encoding: self.encoding, module: self.current_module().to_string() }))
+    }
+    /// Defines the constructors and the eliminator of a data type as macros, in the
+    /// module that is being imported if there is one.
+    pub fn insert_data(&mut self, data: DataType) {
+        let mut names = vec![];
+        for (name, body) in data.macros() {
+            let name = self.qualified(&name);
+            self.insert_macro(&name, vec![], &body);
+            names.push(name);
+        }
+        println!("{}: {}", data.name, names.join(", "));
+        self.data.retain(|declared| declared.name != data.name || declared.module != data.module);
+        self.data.push(data);
+    }
+    /// Parses `#name = body` or `#name(parameters...) = body` into the name, parameters and body.
+    pub fn parse_macro(&self, text: &str, tokens: &mut Tokens) -> Option<Result<MacroDefinition, String>> {
+        let Some(Token::Punct(punct)) = tokens.next() else {
+            return None;
+        };
+        // # will be our macro symbol
+        if punct.punct != "#" {
             return None;
+        }
+        let (name, next, end) = macro_name(&tokens.tokens, tokens.next, punct.span.range.end)?;
+        tokens.next = next;
+        let mut next = tokens.next()?;
+        let parameters = match next {
+            Token::Group(group) if group.open == '(' && group.span.range.start == end => {
+                next = tokens.next()?;
+                Some(group.tokens.tokens)
+            }
+            _ => None,
         };
-        if !tokens.next()?.is_punct_subset("=") {
+        // Without a '=' it's a use of the macro, like `#twice(#succ)`
+        if !next.is_punct_subset("=") {
             return None;
         }
-        let statements = format!("{}", tokens).replace("# ", "#");
-        Some((format!("#{}", ident.ident), statements))
+        let mut names: Vec<String> = vec![];
+        for parameter in parameters.iter().filter(|tokens| !tokens.is_empty()).flat_map(|tokens| tokens.split(|token| token.is_punct_subset(","))) {
+            let [Token::Ident(parameter)] = parameter else {
+                return Some(Err("Expected the name of a parameter".to_string()));
+            };
+            if names.contains(&parameter.ident) {
+                return Some(Err(format!("'{}' is a parameter twice", parameter.ident)));
+            }
+            names.push(parameter.ident.clone());
+        }
+        // Take the body straight from the text, so the spacing between tokens is kept.
+        // It ends at the last token, which leaves out any comment after it.
+        let start = tokens.tokens.get(tokens.next)?.span().range.start;
+        let end = tokens.tokens.last()?.span().range.end;
+        let statements = text[start..end].trim().to_string();
+        Some(Ok((name, names, statements)))
     }
-    pub fn process_message(&self, text: &String) -> Events {
+    pub fn process_message(&self, text: &str) -> Events {
         match text.trim() {
             "exit()" => {
                 Events::Exit
@@ -55,15 +680,290 @@ impl App {
             "clear()" => {
                 Events::Clear
             },
+            "strategy(normal)" => {
+                Events::SetStrategy(Strategy::NormalOrder)
+            },
+            "strategy(cbv)" => {
+                Events::SetStrategy(Strategy::CallByValue)
+            },
+            "mode(untyped)" => {
+                Events::SetMode(Mode::Untyped)
+            },
+            "mode(simple)" => {
+                Events::SetMode(Mode::Simple)
+            },
+            "mode(infer)" => {
+                Events::SetMode(Mode::Inferred)
+            },
+            "mode(systemf)" => {
+                Events::SetMode(Mode::SystemF)
+            },
+            "mode(coc)" => {
+                Events::SetMode(Mode::Constructions)
+            },
+            "usage(unrestricted)" => {
+                Events::SetUsage(Usage::Unrestricted)
+            },
+            "usage(affine)" => {
+                Events::SetUsage(Usage::Affine)
+            },
+            "usage(linear)" => {
+                Events::SetUsage(Usage::Linear)
+            },
+            "encoding(church)" => {
+                Events::SetEncoding(Encoding::Church)
+            },
+            "encoding(scott)" => {
+                Events::SetEncoding(Encoding::Scott)
+            },
+            "encoding(parigot)" => {
+                Events::SetEncoding(Encoding::Parigot)
+            },
+            text if text.starts_with("type(") && text.ends_with(')') => {
+                Events::QueryType(text[5..text.len() - 1].to_string())
+            },
+            text if text.starts_with("synthesize(") && text.ends_with(')') => {
+                Events::Synthesize(text[11..text.len() - 1].to_string())
+            },
+            "macros()" => {
+                Events::ListMacros
+            },
+            text if text.starts_with("show(") && text.ends_with(')') => {
+                Events::ShowMacro(text[5..text.len() - 1].trim().to_string())
+            },
+            text if text.starts_with("delete(") && text.ends_with(')') => {
+                Events::DeleteMacro(text[7..text.len() - 1].trim().to_string())
+            },
+            text if text.starts_with("rename(") && text.ends_with(')') => {
+                // rename(#old, #new)
+                let Some((from, to)) = text[7..text.len() - 1].split_once(',') else {
+                    return Events::Error("Expected rename(#old, #new)".to_string());
+                };
+                Events::RenameMacro(from.trim().to_string(), to.trim().to_string())
+            },
+            "prelude()" => {
+                Events::ListPrelude
+            },
+            "prelude(check)" => {
+                Events::CheckPrelude
+            },
+            text if text.starts_with("prelude(") && text.ends_with(')') => {
+                Events::LoadPrelude(text[8..text.len() - 1].trim().to_string())
+            },
+            text if text.starts_with("load(") && text.ends_with(')') => {
+                Events::LoadFile(text[5..text.len() - 1].trim().to_string())
+            },
+            text if text.starts_with("include ") => {
+                // include "bool.lam", relative to the file it's written in
+                Events::LoadFile(text[8..].trim().trim_matches('"').to_string())
+            },
+            text if text.starts_with("import ") => {
+                // import bool, import "lib/bool.lam", or either with `as name`
+                let (target, alias) = split_alias(&text[7..]);
+                Events::Import(target, alias)
+            },
+            text if text.starts_with("use ") => {
+                // use #bool::and, use #bool::and as #conj or use #bool::*
+                let (target, alias) = split_alias(&text[4..]);
+                Events::Use(target, alias)
+            },
+            text if text.starts_with("#assert_") && let Some(assertion) = Assertion::parse(text) => {
+                // #assert_eq (#add 2 3) 5 and #assert_normalizes e within 1000
+                Events::Assert(assertion)
+            },
+            text if text.starts_with("private ") => {
+                let text = &text[8..];
+                let mut tokens = Lexer::new(text.to_string()).parse();
+                match self.parse_macro(text, &mut tokens) {
+                    Some(Ok((name, parameters, body))) => Events::CreateMacro(name, parameters, body, true),
+                    Some(Err(msg)) => Events::Error(msg),
+                    None => Events::Error("Only macro definitions can be private".to_string()),
+                }
+            },
+            "print(ascii)" => {
+                Events::SetPrinter(PrintOptions { unicode: false, ..self.printer })
+            },
+            "print(unicode)" => {
+                Events::SetPrinter(PrintOptions { unicode: true, ..self.printer })
+            },
+            "print(macros)" => {
+                Events::SetPrinter(PrintOptions { fold: true, ..self.printer })
+            },
+            "print(terms)" => {
+                Events::SetPrinter(PrintOptions { fold: false, ..self.printer })
+            },
+            text if text.starts_with("width(") || text.starts_with("depth(") => {
+                // width(80) or depth(10) set a limit, width(none) or depth(none) remove it
+                let setting = &text[..5];
+                let Some(limit) = text[6..].strip_suffix(')') else {
+                    return Events::Error(format!("Expected {setting}(n) or {setting}(none)"));
+                };
+                let limit = match limit.trim() {
+                    "none" => None,
+                    limit => match limit.parse() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => return Events::Error(format!("Invalid {setting} '{limit}', expected a number above 0 or none")),
+                    },
+                };
+                if setting == "width" {
+                    Events::SetPrinter(PrintOptions { width: limit, ..self.printer })
+                } else {
+                    Events::SetPrinter(PrintOptions { depth: limit, ..self.printer })
+                }
+            },
             _ => {
-                let mut tokens = Lexer::new(text.clone()).parse();
-                if let Some(a) = self.parse_macro(&mut tokens) {
-                    return Events::CreateMacro(a.0, a.1);
+                match self.parse_data(text) {
+                    Some(Ok(data)) => return Events::CreateData(data),
+                    Some(Err(msg)) => return Events::Error(msg),
+                    None => {}
+                }
+                match self.parse_operator(text) {
+                    Some(Ok((associativity, precedence, symbol, term))) => {
+                        return Events::CreateOperator(associativity, precedence, symbol, term);
+                    }
+                    Some(Err(msg)) => return Events::Error(msg),
+                    None => {}
+                }
+                let mut tokens = Lexer::new(text.to_string()).parse();
+                match self.parse_macro(text, &mut tokens) {
+                    Some(Ok((name, parameters, body))) => return Events::CreateMacro(name, parameters, body, false),
+                    Some(Err(msg)) => return Events::Error(msg),
+                    None => {}
                 }
                 Events::Nothing
             }
         }
     }
+    /// Checks the statements with the type system of the current mode,
+    /// and returns their type if the mode has types.
+    pub fn check(&self, statements: &Statements, map: &SourceMap) -> Result<Option<Type>, ASTError> {
+        self.usage.check(statements)?;
+        match self.mode {
+            Mode::Untyped => Ok(None),
+            Mode::Simple => SimpleChecker::new().check(statements).map(Some),
+            Mode::Inferred => {
+                let mut inference = Inference::new(&self.types);
+                let ty = inference.infer(statements, map)?;
+                Ok(Some(Self::print_holes(&inference, ty)))
+            }
+            Mode::SystemF => SystemFChecker::new().check(statements).map(Some),
+            Mode::Constructions => ConstructionsChecker::new().check(statements).map(Some),
+        }
+    }
+    /// Prints the type every hole is expected to have, and the variables it can use.
+    /// Gives back the type of the term with its variables named like the ones of the holes.
+    fn print_holes(inference: &Inference, ty: Type) -> Type {
+        let (types, holes) = inference.holes(&[ty]);
+        for hole in holes {
+            println!("{hole}");
+        }
+        types.into_iter().next().unwrap()
+    }
+    /// Infers the type schemes of a group of macro definitions. The members of the
+    /// group are monomorphic inside of their bodies, and get generalized afterwards.
+    pub fn infer_definitions(&self, definitions: &[(String, String)]) -> Result<Vec<Scheme>, ASTError> {
+        let mut inference = Inference::new(&self.types);
+        let mut taken = HashSet::new();
+        for (_, body) in definitions {
+            identifiers(body, &mut taken);
+        }
+        // Uses of the group inside of the bodies become variables
+        let mut variables = vec![];
+        for (name, _) in definitions {
+            // `#r::loop` becomes `loop`, the name of the module isn't an identifier
+            let short = name.rsplit("::").next().unwrap_or(name).trim_start_matches('#');
+            let variable = fresh_name(short, &taken);
+            taken.insert(variable.clone());
+            let ty = inference.fresh();
+            inference.bind(&variable, Scheme::mono(ty.clone()));
+            variables.push((variable, ty));
+        }
+        for ((name, body), (_, variable)) in definitions.iter().zip(variables.iter()) {
+            let mut body = format!("({})", body.trim());
+            for ((name, _), (variable, _)) in definitions.iter().zip(variables.iter()) {
+                body = replace_macro_reference(&body, name, variable);
+            }
+            let mut map = SourceMap::default();
+            let mut statements = self.parse_expanded(&body, &mut map)?;
+            statements.desugar_cases(&self.data, &map, module_of(name))?;
+            let ty = inference.infer(&statements, &map)?;
+            inference.unify(variable, &ty).map_err(|msg| ASTError::Type(msg, statements.span.clone()))?;
+        }
+        for hole in inference.holes(&[]).1 {
+            println!("{hole}");
+        }
+        for _ in definitions {
+            inference.unbind();
+        }
+        Ok(variables.iter().map(|(_, ty)| inference.generalize(ty)).collect())
+    }
+    /// Prints the type of an expression, or the type scheme of a macro.
+    /// In System F and the Calculus of Constructions the type of a macro is the type of its body,
+    /// and in the Calculus of Constructions types like `#Nat -> Type` have types too.
+    pub fn query_type(&self, text: &str) {
+        let name = text.trim();
+        if !matches!(self.mode, Mode::SystemF | Mode::Constructions) && let Some(definition) = self.macros.get(name) {
+            match self.types.get(name) {
+                Some(scheme) => println!("{name} : {scheme}"),
+                None => println!("{} = {} has no type", definition.head(name), definition.source),
+            }
+            return;
+        }
+        let mut map = SourceMap::default();
+        match self.type_of(text, &mut map) {
+            Ok(ty) => println!("{} : {ty}", text.trim()),
+            Err(err) => println!("{}", self.report(&err, text, &map)),
+        }
+    }
+    /// The type of the text as a term. In the Calculus of Constructions it can be a type too,
+    /// like `Type 1`, which also reads as a term that applies `Type` to a numeral, so it's
+    /// whichever of the two has a type.
+    fn type_of(&self, text: &str, map: &mut SourceMap) -> Result<Type, ASTError> {
+        let statements = self.parse_expanded(text, map).and_then(|mut statements| {
+            statements.desugar_cases(&self.data, map, self.current_module())?;
+            Ok(statements)
+        });
+        match self.mode {
+            Mode::SystemF => SystemFChecker::new().check(&statements?),
+            Mode::Constructions => {
+                let term = statements.and_then(|statements| ConstructionsChecker::new().check(&statements));
+                if term.is_ok() {
+                    return term;
+                }
+                let Ok(ty) = self.calculator.parse_type(text) else {
+                    return term;
+                };
+                let span = Span::new(0..text.len());
+                let ty = ty.expand_macros(&self.macros).map_err(|msg| ASTError::Syntax(msg, span.clone()))?;
+                ConstructionsChecker::new().check_type(&ty, &span)
+            }
+            _ => {
+                let mut inference = Inference::new(&self.types);
+                let ty = inference.infer(&statements?, map)?;
+                Ok(Self::print_holes(&inference, ty))
+            }
+        }
+    }
+    /// Prints the smallest terms that have the type, or that it has none.
+    pub fn synthesize(&self, text: &str) {
+        let ty = match self.calculator.parse_type(text) {
+            Ok(ty) => ty,
+            Err(err) => {
+                println!("{}", self.report(&err, text, &SourceMap::default()));
+                return;
+            }
+        };
+        match synthesize(&ty, SYNTHESIZED_TERMS) {
+            Err(msg) => println!("{msg}"),
+            Ok(None) => println!("{ty} has no terms, it isn't provable"),
+            Ok(Some(terms)) if terms.is_empty() => println!("{ty} has terms, but none with a size up to {MAX_SIZE}"),
+            Ok(Some(terms)) => {
+                for term in terms {
+                    println!("{}", self.printer.statements(&term));
+                }
+            }
+        }
+    }
     pub fn clear_terminal_screen() {
         let result = if cfg!(target_os = "windows") {
             Command::new("cmd").args(["/c", "cls"]).spawn()
@@ -77,49 +977,274 @@ impl App {
             print!("{esc}c", esc = 27 as char);
         }
     }
-    pub fn apply_macros(&mut self, text: &mut String) {
-        for (from, to) in self.macros.iter() {
-            *text = text.replace(from.as_str(), to.as_str());
-            // println!("CHANGE {text}");
-        }
-    }
     pub fn run(&mut self) {
-        let mut events = Events::Nothing;
         loop {
             let mut text = String::new();
-            if let Err(_) = std::io::stdin().read_line(&mut text) {
-                continue;
-            }
-            events = self.process_message(&text);
-            match events {
-                Events::Nothing => {}
-                Events::Clear => {
-                    Self::clear_terminal_screen();
-                    continue
-                },
-                Events::Error => continue,
-                Events::Exit => break,
-                Events::CreateMacro(from, to) => {
-                    self.insert_macro(&from, &to);
-                    continue;
-                }
+            match std::io::stdin().read_line(&mut text) {
+                // The end of the input
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(_) => continue,
             }
-            self.apply_macros(&mut text);
-            match self.calculator.input(&text) {
-                Err(ASTError::EmptyTokenList) => {}
-                Err(msg) => {
-                    println!("{}", msg);
-                    continue;
-                }
-                Ok(_) => {
-
+            if !self.execute(&text) {
+                break;
+            }
+        }
+    }
+    /// Runs a line of the REPL or an entry of a file, false when it asks to exit.
+    pub fn execute(&mut self, text: &str) -> bool {
+        // The macros of a module that is being imported are named after it
+        let text = &match &self.namespace {
+            Some(namespace) => namespace.qualify(text),
+            None => text.to_string(),
+        };
+        let mut lexer = Lexer::with_operators(text.to_string(), self.calculator.operators.symbols());
+        lexer.parse();
+        if let Err(err) = check_comments(&lexer) {
+            self.error(self.report(&err, text, &SourceMap::default()));
+            return true;
+        }
+        if let Err(msg) = self.check_visibility(text) {
+            self.error(format!("{}{msg}", self.location()));
+            return true;
+        }
+        match self.process_message(text) {
+            Events::Nothing => {}
+            Events::Clear => {
+                Self::clear_terminal_screen();
+                return true;
+            }
+            Events::Exit => return false,
+            Events::SetStrategy(strategy) => {
+                self.set_strategy(strategy);
+                return true;
+            }
+            Events::QueryType(text) => {
+                self.query_type(&text);
+                return true;
+            }
+            Events::Synthesize(text) => {
+                self.synthesize(&text);
+                return true;
+            }
+            Events::SetMode(mode) => {
+                self.mode = mode;
+                return true;
+            }
+            Events::SetUsage(usage) => {
+                self.usage = usage;
+                return true;
+            }
+            Events::SetEncoding(encoding) => {
+                self.encoding = encoding;
+                return true;
+            }
+            Events::SetPrinter(printer) => {
+                self.printer = printer;
+                return true;
+            }
+            Events::Error(msg) => {
+                self.error(msg);
+                return true;
+            }
+            Events::CreateOperator(associativity, precedence, symbol, term) => {
+                self.insert_operator(associativity, precedence, &symbol, &term);
+                return true;
+            }
+            Events::CreateData(data) => {
+                self.insert_data(data);
+                return true;
+            }
+            Events::CreateMacro(from, parameters, to, private) => {
+                self.insert_macro(&from, parameters, &to);
+                if private && self.macros.contains_key(&from) {
+                    self.private.insert(from);
+                } else {
+                    self.private.remove(&from);
                 }
-                _ => {}
+                return true;
             }
-            while self.calculator.step().is_running() {
-                println!("{}", self.calculator.statements());
-                
+            Events::ListMacros => {
+                self.list_macros();
+                return true;
             }
+            Events::ShowMacro(name) => {
+                self.show_macro(&name);
+                return true;
+            }
+            Events::DeleteMacro(name) => {
+                self.delete_macro(&name);
+                return true;
+            }
+            Events::RenameMacro(from, to) => {
+                self.rename_macro(&from, &to);
+                return true;
+            }
+            Events::ListPrelude => {
+                Self::list_prelude();
+                return true;
+            }
+            Events::LoadPrelude(name) => {
+                self.load_prelude(&name);
+                return true;
+            }
+            Events::CheckPrelude => {
+                Self::check_prelude();
+                return true;
+            }
+            Events::LoadFile(path) => {
+                self.load_file(&path);
+                return true;
+            }
+            Events::Import(target, alias) => {
+                self.import(&target, alias.as_deref());
+                return true;
+            }
+            Events::Use(target, alias) => {
+                self.use_macro(&target, alias.as_deref());
+                return true;
+            }
+            Events::Assert(assertion) => {
+                self.assert(&assertion);
+                return true;
+            }
+        }
+        let mut map = SourceMap::default();
+        match self.parse_expanded(text, &mut map) {
+            Err(ASTError::EmptyTokenList) => return true,
+            Err(err) => {
+                self.error(self.report(&err, text, &map));
+                return true;
+            }
+            Ok(statements) => self.calculator.input(statements),
         }
+        let module = self.current_module().to_string();
+        if let Err(err) = self.calculator.desugar_cases(&self.data, &map, &module) {
+            self.error(self.report(&err, text, &map));
+            return true;
+        }
+        let statements = self.calculator.statements();
+        if statements.statements.is_empty() {
+            return true;
+        }
+        let ty = match self.check(&statements, &map) {
+            Err(err) => {
+                self.error(self.report(&err, text, &map));
+                return true;
+            }
+            Ok(ty) => ty,
+        };
+        if let Some(ty) = &ty {
+            println!(": {ty}");
+        }
+        self.calculator.erase_types();
+        while self.calculator.step().is_running() {
+            let statements = self.calculator.statements();
+            println!("{}", self.print_evaluated(statements));
+        }
+        // The type says how the normal form should be read
+        if let Some(value) = ty.and_then(|ty| read_back(&ty, &self.calculator.statements(), &self.printer)) {
+            println!("= {value}");
+        }
+        true
+    }
+}
+/// Splits `target as alias` of `import` and `use`.
+fn split_alias(text: &str) -> (String, Option<String>) {
+    match text.rsplit_once(" as ") {
+        Some((target, alias)) => (target.trim().to_string(), Some(alias.trim().to_string())),
+        None => (text.trim().to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app that ran the lines.
+    fn run(lines: &[&str]) -> App {
+        let mut app = App::new();
+        for line in lines {
+            app.execute(line);
+        }
+        app
+    }
+
+    /// The type of the text, printed.
+    fn type_of(app: &App, text: &str) -> String {
+        app.type_of(text, &mut SourceMap::default()).unwrap().to_string()
+    }
+
+    #[test]
+    fn universes_have_types() {
+        let app = run(&["mode(coc)"]);
+        assert_eq!(type_of(&app, "Type 1"), "Type 2");
+        assert_eq!(type_of(&app, "Type 0 -> Type 0"), "Type 1");
+        assert_eq!(type_of(&app, "\\x:Type. x"), "Type -> Type");
+    }
+
+    /// The normal form of the text.
+    fn value(app: &App, text: &str) -> Statements {
+        app.normal_form(text, 10_000).unwrap().unwrap()
     }
-}
\ No newline at end of file
+
+    fn parse(app: &App, text: &str) -> Statements {
+        app.calculator.parse(text).unwrap()
+    }
+
+    #[test]
+    fn users_see_a_redefinition() {
+        let mut app = run(&["#pair = \\a b k. k a b", "#first = \\p. p (\\a b. a)", "#pick = \\x. #first (#pair x z)"]);
+        assert!(value(&app, "#pick y").alpha_equivalent(&parse(&app, "y")));
+        app.execute("#first = \\p. p (\\a b. b)");
+        assert!(value(&app, "#pick y").alpha_equivalent(&parse(&app, "z")));
+    }
+
+    #[test]
+    fn macros_can_be_used_before_they_are_defined() {
+        let mut app = run(&["#later = \\x. #id x"]);
+        assert!(!app.types.contains_key("#later"));
+        app.execute("#id = \\y. y");
+        assert!(value(&app, "#later a").alpha_equivalent(&parse(&app, "a")));
+        assert!(app.types.contains_key("#later"));
+    }
+
+    #[test]
+    fn cycle_of_aliases_is_an_error() {
+        let app = run(&["#a = #b", "#b = #a"]);
+        assert!(app.macros.contains_key("#a"));
+        assert!(!app.macros.contains_key("#b"));
+        assert_eq!(app.results.errors, 1);
+    }
+
+    #[test]
+    fn strategy_change_recompiles_recursive_macros() {
+        let mut app = run(&["#loop = \\n. #loop n"]);
+        let compiled = |strategy| compile_recursive(&[("#loop".to_string(), "\\n. #loop n".to_string())], strategy).remove(0).1;
+        let body = |app: &App| app.macros["#loop"].body.clone().unwrap();
+        assert!(body(&app).alpha_equivalent(&parse(&app, &compiled(Strategy::NormalOrder))));
+        app.execute("strategy(cbv)");
+        assert!(body(&app).alpha_equivalent(&parse(&app, &compiled(Strategy::CallByValue))));
+        app.execute("strategy(normal)");
+        assert!(body(&app).alpha_equivalent(&parse(&app, &compiled(Strategy::NormalOrder))));
+    }
+
+    #[test]
+    fn rename_that_breaks_a_user_changes_nothing() {
+        let mut app = run(&["infixl 5 ++ = \\a b. a", "#f = x", "#g = y ++ z ++ #f", "infix 5 ++ = \\a b. b"]);
+        app.rename_macro("#f", "#h");
+        assert!(app.macros.contains_key("#f") && !app.macros.contains_key("#h"));
+        assert_eq!(app.macros["#g"].source, "y ++ z ++ #f");
+        assert_eq!(app.results.errors, 1);
+    }
+
+    #[test]
+    fn rename_updates_the_users() {
+        let mut app = run(&["#f = \\x. x", "#g = #f #f", "#loop = \\n. #loop n"]);
+        app.rename_macro("#f", "#h");
+        app.rename_macro("#loop", "#spin");
+        assert_eq!(app.macros["#g"].source, "#h #h");
+        assert_eq!(app.macros["#spin"].source, "\\n. #spin n");
+        assert!(!app.macros.contains_key("#loop"));
+    }
+}
@@ -0,0 +1,260 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use crate::{app::{App, Events, files::entries, recursion::{macro_references, replace_macro_references}}, data::is_name, expansion::{is_macro_name, module_of}, prelude::{self, Module}};
+
+/// A module that is being imported, the macros it defines are named after it.
+pub struct Namespace {
+    pub name: String,
+    /// The names the module uses for its own macros, and the names they are defined
+    /// with, like `#and` and `#bool::and`.
+    pub scope: BTreeMap<String, String>,
+}
+
+impl Namespace {
+    /// The name of a macro of the module, `#bool::and` for `#and`.
+    fn qualified(&self, name: &str) -> String {
+        format!("#{}::{}", self.name, name.trim_start_matches('#'))
+    }
+    /// Gives the macros of the module their names in the text. Only whole
+    /// names are replaced, so `#and` doesn't touch `#and2` or `#bool::and`.
+    pub fn qualify(&self, text: &str) -> String {
+        replace_macro_references(text, &self.scope)
+    }
+}
+
+impl App {
+    /// Loads a module of the prelude, or a `.lam` file for a quoted path, into a namespace so its
+    /// macros are named like `#bool::and`. The namespace is the alias, or the name of the module or file.
+    pub fn import(&mut self, target: &str, alias: Option<&str>) {
+        let file = target.strip_prefix('"').and_then(|target| target.strip_suffix('"'));
+        let name = match (alias, file) {
+            (Some(alias), _) => alias.to_string(),
+            (None, Some(file)) => Path::new(file).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+            (None, None) => target.to_string(),
+        };
+        if !is_name(&name) {
+            self.error(format!("{}'{name}' can't be the name of a module", self.location()));
+            return;
+        }
+        match file {
+            Some(file) => self.import_file(file, name),
+            None => match prelude::module(target) {
+                Some(module) => self.import_prelude(module, name, &mut vec![]),
+                None => self.error(format!("{}'{target}' isn't a module of the prelude, files are imported with a quoted path", self.location())),
+            },
+        }
+    }
+    /// Imports the modules of the prelude that the module uses under their own names, and then the module.
+    fn import_prelude(&mut self, module: &'static Module, name: String, imported: &mut Vec<&'static str>) {
+        imported.push(module.name);
+        let mut namespace = Namespace { name, scope: BTreeMap::new() };
+        for required in module.requires.iter().filter_map(|required| prelude::module(required)) {
+            if !imported.contains(&required.name) {
+                self.import_prelude(required, required.name.to_string(), imported);
+            }
+            for entry in required.entries {
+                namespace.scope.insert(entry.name.to_string(), format!("#{}::{}", required.name, entry.name.trim_start_matches('#')));
+            }
+        }
+        for entry in module.entries {
+            namespace.scope.insert(entry.name.to_string(), namespace.qualified(entry.name));
+        }
+        let mut names = vec![];
+        for entry in module.entries {
+            let name = namespace.qualified(entry.name);
+            self.insert_macro(&name, vec![], &namespace.qualify(entry.body));
+            names.push(name);
+        }
+        println!("{}: {}", namespace.name, names.join(", "));
+    }
+    /// Runs the file with its macros in the namespace. Every macro the file and the files
+    /// it includes define is found first, so they can be used before they are defined.
+    /// The operators the file declares are left out of the table once it has run.
+    fn import_file(&mut self, path: &str, name: String) {
+        let mut names = vec![];
+        self.defined_names(&self.resolve(path), &mut vec![], &mut names);
+        let mut namespace = Namespace { name, scope: BTreeMap::new() };
+        for name in names {
+            let qualified = namespace.qualified(&name);
+            namespace.scope.insert(name, qualified);
+        }
+        self.module_operators.remove(&namespace.name);
+        let operators = self.calculator.operators.clone();
+        let outer = self.namespace.replace(namespace);
+        self.load_file(path);
+        self.namespace = outer;
+        self.calculator.operators = operators;
+    }
+    /// The macros that the file defines, along with the constructors and eliminators
+    /// of its data types, and the files it includes. Files that can't be read
+    /// are left out, loading them reports the error.
+    fn defined_names(&self, path: &Path, visited: &mut Vec<PathBuf>, names: &mut Vec<String>) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return;
+        };
+        if visited.contains(&canonical) {
+            return;
+        }
+        visited.push(canonical);
+        for (_, entry) in entries(&text).unwrap_or_default() {
+            match self.process_message(&entry) {
+                Events::CreateMacro(name, ..) => names.push(name),
+                Events::CreateData(data) => names.extend(data.macros().into_iter().map(|(name, _)| name)),
+                Events::Use(target, alias) if !target.ends_with("::*") => {
+                    names.push(alias.unwrap_or_else(|| short_name(&target)));
+                }
+                Events::LoadFile(included) => {
+                    let included = path.parent().map_or(PathBuf::from(&included), |directory| directory.join(&included));
+                    self.defined_names(&included, visited, names);
+                }
+                _ => {}
+            }
+        }
+    }
+    /// Defines `#and` as `#bool::and`, or as `alias`, and `#bool::*` does it for every macro
+    /// of `bool`, and declares the operators of `bool` too.
+    pub fn use_macro(&mut self, target: &str, alias: Option<&str>) {
+        if let Some(module) = target.strip_suffix("::*").and_then(|module| module.strip_prefix('#')) {
+            if alias.is_some() {
+                self.error(format!("{}The macros of a whole module can't be renamed", self.location()));
+                return;
+            }
+            let mut targets: Vec<String> = self.macros.keys()
+                .filter(|name| module_of(name) == module && self.visible(name))
+                .cloned()
+                .collect();
+            let operators = self.module_operators.get(module).map_or(vec![], |operators| operators.operators.clone());
+            if targets.is_empty() && operators.is_empty() {
+                self.error(format!("{}'{module}' has no macros", self.location()));
+                return;
+            }
+            targets.sort();
+            let mut aliases: Vec<String> = targets.iter().map(|target| self.alias(&short_name(target), target)).collect();
+            for operator in operators {
+                aliases.push(format!("'{}'", operator.symbol));
+                self.declare_operator(operator);
+            }
+            println!("{}: {}", module, aliases.join(", "));
+            return;
+        }
+        if !self.macros.contains_key(target) {
+            self.error(format!("{}'{target}' is not defined", self.location()));
+            return;
+        }
+        match alias {
+            Some(alias) if !is_macro_name(alias) => {
+                self.error(format!("{}'{alias}' can't be used as the name of a macro", self.location()));
+            }
+            // The alias was already named after the module being imported
+            Some(alias) => {
+                let definition = &self.macros[target];
+                let (parameters, body) = (definition.parameters.clone(), definition.head(target));
+                self.insert_macro(alias, parameters, &body);
+            }
+            None => {
+                self.alias(&short_name(target), target);
+            }
+        }
+    }
+    /// Defines the alias as the target, in the module that is being imported if there
+    /// is one, and returns the name it got.
+    fn alias(&mut self, alias: &str, target: &str) -> String {
+        let name = self.qualified(alias);
+        let definition = &self.macros[target];
+        let (parameters, body) = (definition.parameters.clone(), definition.head(target));
+        self.insert_macro(&name, parameters, &body);
+        name
+    }
+    /// The name a macro defined by the text that runs gets, which is named
+    /// after the module that is being imported if there is one.
+    pub fn qualified(&mut self, name: &str) -> String {
+        match self.namespace.as_mut() {
+            Some(namespace) => {
+                let qualified = namespace.qualified(name);
+                namespace.scope.insert(name.to_string(), qualified.clone());
+                qualified
+            }
+            None => name.to_string(),
+        }
+    }
+    /// The module of the text that runs, which is the namespace of the file being imported.
+    pub fn current_module(&self) -> &str {
+        self.namespace.as_ref().map_or("", |namespace| &namespace.name)
+    }
+    /// Private macros can only be used by their own module.
+    fn visible(&self, name: &str) -> bool {
+        !self.private.contains(name) || module_of(name) == self.current_module()
+    }
+    /// Checks that the macros the text uses can be used from the current module.
+    pub fn check_visibility(&self, text: &str) -> Result<(), String> {
+        match macro_references(text).into_iter().find(|name| !self.visible(name)) {
+            Some(name) if module_of(&name).is_empty() => Err(format!("'{name}' is private")),
+            Some(name) => Err(format!("'{name}' is private to {}", module_of(&name))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The name of a macro without its module, `#and` for `#bool::and`.
+fn short_name(name: &str) -> String {
+    match name.rsplit_once("::") {
+        Some((_, short)) => format!("#{short}"),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::files::test_directory;
+
+    use super::*;
+
+    /// Writes the files into a directory of their own and imports the first one.
+    fn import(test: &str, files: &[(&str, &str)]) -> App {
+        let directory = test_directory(test);
+        for (name, text) in files {
+            std::fs::write(directory.join(name), text).unwrap();
+        }
+        let mut app = App::new();
+        app.execute(&format!("import \"{}\"", directory.join(files[0].0).display()));
+        app
+    }
+
+    #[test]
+    fn qualifies_only_whole_names() {
+        let namespace = Namespace { name: "m".to_string(), scope: BTreeMap::from([("#and".to_string(), "#m::and".to_string())]) };
+        assert_eq!(namespace.qualify("#and #and2 #bool::and"), "#m::and #and2 #bool::and");
+    }
+
+    #[test]
+    fn data_types_belong_to_their_module() {
+        let mut app = import("data_types_belong_to_their_module", &[("a.lam", "data List = Nil | Cons x List\n#len = \\l. case l of { Nil -> 0; Cons x r -> 1 }")]);
+        app.execute("encoding(scott)");
+        app.execute("data List = Nil | Cons x List");
+        for name in ["#a::Nil", "#a::Cons", "#a::elimList", "#a::caseList", "#Nil"] {
+            assert!(app.macros.contains_key(name), "{name}");
+        }
+        assert_eq!(app.data.len(), 2);
+        // The case of the module still takes the Church lists of the module apart
+        let one = app.normal_form("#a::len (#a::Cons 1 #a::Nil)", 1000).unwrap().unwrap();
+        assert!(one.alpha_equivalent(&app.normal_form("1", 10).unwrap().unwrap()));
+    }
+
+    #[test]
+    fn operators_stay_in_their_module() {
+        let mut app = import("operators_stay_in_their_module", &[("a.lam", "infixl 6 +++ = \\a b. a\n#first = \\a b. a +++ b")]);
+        assert!(app.calculator.operators.operators.is_empty());
+        assert!(app.macros.contains_key("#a::first"));
+        app.execute("use #a::*");
+        assert_eq!(app.calculator.operators.symbols(), ["+++"]);
+    }
+
+    #[test]
+    fn recursive_macros_of_a_module_have_types() {
+        let mut app = import("recursive_macros_of_a_module_have_types", &[("r.lam", "#loop = \\x. #loop x")]);
+        app.execute("use #r::loop");
+        assert_eq!(app.types["#r::loop"].to_string(), "a -> b");
+        assert_eq!(app.types["#loop"].to_string(), "a -> b");
+    }
+}
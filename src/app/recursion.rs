@@ -0,0 +1,234 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, ops::Range};
+
+use gelato_parser::lexer::{Lexer, tokens::{Token, Tokens}};
+
+use crate::{app::state::Strategy, ast::fresh_name, expansion::macro_name};
+
+/// Y = λf.(λx.f (x x)) (λx.f (x x)), only usable with normal order.
+pub const Y_COMBINATOR: &str = "(\\f.(\\x.f (x x)) (\\x.f (x x)))";
+/// Z = λf.(λx.f (λv.x x v)) (λx.f (λv.x x v)), the call by value fixed point. It only delays the
+/// recursive call itself, so the branches of a conditional still need to be functions that get
+/// applied once they're picked, or both get evaluated and the recursion never ends.
+pub const Z_COMBINATOR: &str = "(\\f.(\\x.f (\\v.x x v)) (\\x.f (\\v.x x v)))";
+
+pub fn fixpoint(strategy: Strategy) -> &'static str {
+    match strategy {
+        Strategy::NormalOrder => Y_COMBINATOR,
+        Strategy::CallByValue => Z_COMBINATOR,
+    }
+}
+
+/// Every use of a macro in the tokens, with the offsets its '#' and name take up.
+fn collect_uses(tokens: &Tokens, uses: &mut Vec<(String, Range<usize>)>) {
+    for (i, token) in tokens.tokens.iter().enumerate() {
+        match token {
+            Token::Punct(punct) if punct.is_punct("#") => {
+                if let Some((name, _, end)) = macro_name(&tokens.tokens, i + 1, punct.span.range.end) {
+                    uses.push((name, punct.span.range.start..end));
+                }
+            }
+            Token::Group(group) => collect_uses(&group.tokens, uses),
+            _ => {}
+        }
+    }
+}
+/// The uses of macros in the text, read the same way the parser reads them into
+/// macros of the syntax tree, so `#f` is never a part of `#f2` or `#f::g`.
+fn macro_uses(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut uses = vec![];
+    collect_uses(&Lexer::new(text.to_string()).parse(), &mut uses);
+    uses
+}
+/// Every macro name (with its '#') used in the text, in order of appearance.
+pub fn macro_references(text: &str) -> Vec<String> {
+    let mut references: Vec<String> = vec![];
+    for (name, _) in macro_uses(text) {
+        if !references.contains(&name) {
+            references.push(name);
+        }
+    }
+    references
+}
+/// Every identifier used in the text, including bound variables.
+pub fn identifiers(text: &str, idents: &mut HashSet<String>) {
+    fn collect(tokens: Tokens, idents: &mut HashSet<String>) {
+        for token in tokens {
+            match token {
+                Token::Ident(ident) => {
+                    idents.insert(ident.ident);
+                }
+                Token::Group(group) => collect(group.tokens, idents),
+                _ => {}
+            }
+        }
+    }
+    collect(Lexer::new(text.to_string()).parse(), idents);
+}
+/// Replaces the uses of the macro `name`, but not of the macros
+/// that only start with the same characters.
+pub fn replace_macro_reference(text: &str, name: &str, with: &str) -> String {
+    replace_macro_references(text, &BTreeMap::from([(name.to_string(), with.to_string())]))
+}
+/// Replaces the uses of every macro in `names` at once, so a replacement is never replaced again.
+pub fn replace_macro_references(text: &str, names: &BTreeMap<String, String>) -> String {
+    let mut text = text.to_string();
+    // From the last use, so the offsets of the others stay where they are
+    for (name, range) in macro_uses(&text).into_iter().rev() {
+        if let Some(with) = names.get(&name) {
+            text.replace_range(range, with);
+        }
+    }
+    text
+}
+/// Name of the i'th variable of a tuple, following a, b, ..., z, aa, ab...
+fn tuple_variable(mut i: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'a' + (i % 26) as u8) as char);
+        if i < 26 {
+            return name;
+        }
+        i = i / 26 - 1;
+    }
+}
+/// λa.λb...λn.<i'th variable>, selects one element out of a church tuple.
+fn projection(i: usize, len: usize) -> String {
+    let mut ret = String::from("(");
+    for j in 0..len {
+        ret.push_str(&format!("\\{}.", tuple_variable(j)));
+    }
+    ret.push_str(&tuple_variable(i));
+    ret.push(')');
+    ret
+}
+/// Compiles a group of definitions that reference each other into
+/// definitions that don't, using the fixed point combinator of the strategy.
+///
+/// A single definition `#f = body` becomes `FIX (λf.body)`. Mutually recursive
+/// definitions are tupled: `FIX (λrec.λsel.sel body_0 ... body_n)`, where every
+/// `#name_i` is replaced with `rec` applied to the projection of the i'th element.
+/// The bodies only have their references to the group replaced, other macros
+/// still have to be applied.
+pub fn compile_recursive(group: &[(String, String)], strategy: Strategy) -> Vec<(String, String)> {
+    let fix = fixpoint(strategy);
+    let mut taken = HashSet::new();
+    for (_, body) in group {
+        identifiers(body, &mut taken);
+    }
+    let rec = fresh_name("rec", &taken);
+    if let [(name, body)] = group {
+        let body = replace_macro_reference(body, name, &rec);
+        return vec![(name.clone(), format!("({fix} (\\{rec}.{body}))"))];
+    }
+    let sel = fresh_name("sel", &taken);
+    let mut tuple = format!("\\{rec}.\\{sel}.{sel}");
+    for (_, body) in group {
+        let mut body = body.clone();
+        for (i, (name, _)) in group.iter().enumerate() {
+            let element = format!("({rec} {})", projection(i, group.len()));
+            body = replace_macro_reference(&body, name, &element);
+        }
+        tuple.push_str(&format!(" ({body})"));
+    }
+    group.iter().enumerate().map(|(i, (name, _))| {
+        (name.clone(), format!("(({fix} ({tuple})) {})", projection(i, group.len())))
+    }).collect()
+}
+/// Splits the macros into the groups that reference each other, the strongly connected
+/// components of the graph where every macro points at the macros it uses. A group
+/// always comes after the groups it uses, so they can be compiled in order.
+pub fn recursive_groups(references: &BTreeMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    /// Tarjan's algorithm, a group is done once the search gets back to its first macro.
+    struct Search<'a> {
+        references: &'a BTreeMap<String, Vec<String>>,
+        index: HashMap<&'a str, usize>,
+        lowest: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        groups: Vec<Vec<String>>,
+    }
+    impl<'a> Search<'a> {
+        fn visit(&mut self, name: &'a str) {
+            let index = self.index.len();
+            self.index.insert(name, index);
+            self.lowest.insert(name, index);
+            self.stack.push(name);
+            for next in self.references[name].iter().filter(|next| self.references.contains_key(*next)) {
+                let next = next.as_str();
+                if !self.index.contains_key(next) {
+                    self.visit(next);
+                    self.lowest.insert(name, self.lowest[name].min(self.lowest[next]));
+                } else if self.stack.contains(&next) {
+                    self.lowest.insert(name, self.lowest[name].min(self.index[next]));
+                }
+            }
+            if self.lowest[name] == index {
+                let start = self.stack.iter().position(|member| *member == name).unwrap();
+                let mut group: Vec<String> = self.stack.drain(start..).map(str::to_string).collect();
+                group.sort();
+                self.groups.push(group);
+            }
+        }
+    }
+    let mut search = Search { references, index: HashMap::new(), lowest: HashMap::new(), stack: vec![], groups: vec![] };
+    for name in references.keys() {
+        if !search.index.contains_key(name.as_str()) {
+            search.visit(name);
+        }
+    }
+    search.groups
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+
+    use super::*;
+
+    #[test]
+    fn replaces_only_the_exact_macro() {
+        assert_eq!(replace_macro_reference("#f2 #f (#ff #f) #f::g #m::f", "#f", "#h"), "#f2 #h (#ff #h) #f::g #m::f");
+        assert_eq!(replace_macro_reference("\\x. #f(x, #f) -- #f", "#f", "#h"), "\\x. #h(x, #h) -- #f");
+    }
+
+    #[test]
+    fn replaces_several_macros_at_once() {
+        let names = BTreeMap::from([("#a".to_string(), "#b".to_string()), ("#b".to_string(), "#a".to_string())]);
+        assert_eq!(replace_macro_references("#a #b", &names), "#b #a");
+    }
+
+    #[test]
+    fn recursion_uses_the_fixed_point_combinator_of_the_strategy() {
+        let group = [("#f".to_string(), "\\n. #f n".to_string())];
+        let [(_, normal)] = compile_recursive(&group, Strategy::NormalOrder).try_into().unwrap();
+        assert!(normal.starts_with(&format!("({Y_COMBINATOR} (\\rec.")) && !normal.contains("#f"), "{normal}");
+        let [(_, value)] = compile_recursive(&group, Strategy::CallByValue).try_into().unwrap();
+        assert!(value.starts_with(&format!("({Z_COMBINATOR} (\\rec.")), "{value}");
+    }
+
+    #[test]
+    fn mutual_recursion_is_tupled() {
+        let group = [
+            ("#even".to_string(), "\\n. #iszero n #true (#odd (#pred n))".to_string()),
+            ("#odd".to_string(), "\\n. #iszero n #false (#even (#pred n))".to_string()),
+        ];
+        let compiled = compile_recursive(&group, Strategy::NormalOrder);
+        assert_eq!(compiled.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["#even", "#odd"]);
+        for (_, body) in compiled.iter() {
+            assert!(!body.contains("#even") && !body.contains("#odd"), "{body}");
+        }
+        assert!(compiled[0].1.ends_with(&format!(" {})", projection(0, 2))));
+        assert!(compiled[1].1.ends_with(&format!(" {})", projection(1, 2))));
+    }
+
+    #[test]
+    fn mutually_recursive_macros_evaluate() {
+        let mut app = App::new();
+        app.execute("prelude(nat)");
+        app.execute("#even = \\n. #if (#iszero n) #true (#odd (#pred n))");
+        app.execute("#odd = \\n. #if (#iszero n) #false (#even (#pred n))");
+        let value = |text: &str| app.normal_form(text, 100_000).unwrap().unwrap();
+        assert!(value("#even 4").alpha_equivalent(&value("#true")));
+        assert!(value("#odd 3").alpha_equivalent(&value("#true")));
+        assert!(value("#even 3").alpha_equivalent(&value("#false")));
+    }
+}
@@ -1,8 +1,6 @@
-use std::collections::LinkedList;
+use std::collections::HashSet;
 
-use gelato_parser::lexer::Lexer;
-
-use crate::{ast::{Abstraction, FromTokens, Group, Statement, Statements, Term}, error::ASTError};
+use crate::ast::{Group, Statement, Statements, Term, fresh_name};
 
 #[repr(i8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -16,44 +14,74 @@ impl ControlFlow {
     }
 }
 
+/// The order in which redexes are picked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strategy {
+    /// Leftmost outermost redex first, reducing under abstractions.
+    #[default]
+    NormalOrder,
+    /// Arguments are reduced before they are substituted, and
+    /// abstractions are never reduced under.
+    CallByValue,
+}
+
 pub struct CalculatorState {
     pub statements: Statements,
+    pub strategy: Strategy,
 }
 
 impl CalculatorState {
-    pub fn new(lexer: &mut Lexer) -> Result<Self, ASTError> {
-        let mut tokens = lexer.parse();
-        let statements = Statements::from_tokens(&mut tokens)?;
-        Ok(Self { statements })
+    pub fn new(statements: Statements, strategy: Strategy) -> Self {
+        Self { statements, strategy }
     }
     /// replaces all variables in the abstraction that aren't renamed
     /// in sub abstraction.
     pub fn replace_names(statements: &mut Statements, from: &Term, to: &Statement) {
+        Self::substitute(statements, from, to, &to.free_variables());
+    }
+    fn substitute(statements: &mut Statements, from: &Term, to: &Statement, free: &HashSet<String>) {
         // Iterate over all the statements to change any terms inside of them
         // that match with 'from', with 'to'
         for statement in statements.statements.iter_mut() {
             match statement {
                 // If the variable is within another abstraction then we need to check for
                 // 1. If the variable has been renamed in another abstraction, then ignore
-                // 2. If the variable has not been renamed, replace all variables that match 
+                // 2. If the abstraction would capture a free variable of 'to', rename it first
+                // 3. Replace all variables that match
                 Statement::Abstraction(abstraction) => {
                     if &abstraction.variable == from { // If the variable has been renamed then ignore
                         continue;
-                    } else { // rename all variables in the sub abstraction
-                        Self::replace_names(&mut abstraction.next, from, to); 
                     }
+                    let variable = abstraction.variable.name.ident.clone();
+                    if free.contains(&variable) && abstraction.next.free_variables().contains(&from.name.ident) {
+                        let mut taken = abstraction.next.names();
+                        taken.extend(free.iter().cloned());
+                        let fresh = fresh_name(&variable, &taken);
+                        abstraction.next.rename(&variable, &fresh);
+                        abstraction.variable.name.ident = fresh;
+                    }
+                    Self::substitute(&mut abstraction.next, from, to, free);
                 }
                 Statement::Group(group) => {
-                    Self::replace_names(&mut group.statements, from, to); 
+                    Self::substitute(&mut group.statements, from, to, free);
+                }
+                Statement::TypeAbstraction(abstraction) => {
+                    Self::substitute(&mut abstraction.next, from, to, free);
                 }
+                Statement::TypeApplication(_) | Statement::Hole(_) => {}
+                Statement::Case(_) => unreachable!("cases are desugared before evaluation"),
+                Statement::Macro(_) => unreachable!("macros are expanded before evaluation"),
                 Statement::Term(term) => {
-                    // If the variable matches with a term, and to is an abstraction
-                    // we can replace it in a group.
-                    if to.is_abstraction() {
-                        let mut statements = LinkedList::new();
-                        statements.push_back(to.clone());
-                        *statement = Statement::Group(Group { statements: Statements { statements } });
-                    } else if term == from {
+                    if term != from {
+                        continue;
+                    }
+                    // Abstractions are kept inside of a group so they don't
+                    // swallow the statements that follow them.
+                    if let Statement::Abstraction(_) = to {
+                        let span = to.span().clone();
+                        let statements = Statements { statements: [to.clone()].into(), span: span.clone() };
+                        *statement = Statement::Group(Group { statements, span });
+                    } else {
                         *statement = to.clone();
                     }
                 }
@@ -69,87 +97,165 @@ impl CalculatorState {
             statements.statements.push_front(statement);
         }
     }
-    fn apply_abstraction(parent_group: &mut Group, abstraction: &mut Abstraction, input: Option<Statement>) -> ControlFlow {
-        if let Some(input) = input {
-            // If it has input, apply the abstraction to replace the names
-            // then push the statements of the abstraction onto the group
-            Self::replace_names(&mut abstraction.next, &abstraction.variable, &input);
-            // Check if its being curried
-            if abstraction.next.statements.front().unwrap().is_abstraction() {
-                parent_group.statements.statements.push_front(Statement::Group(Group { statements: abstraction.next.clone() }));
-            } else {
-                Self::push_statements_front(&mut parent_group.statements, &abstraction.next);
+    /// Removes the parenthesis at the front of the statements as long as
+    /// that doesn't change what the statements mean. `((a b) c)` is `a b c`,
+    /// but `(λx.x) c` has to keep its group.
+    fn flatten_front(statements: &mut Statements) {
+        loop {
+            let flatten = match statements.statements.front() {
+                Some(Statement::Group(group)) => {
+                    statements.statements.len() == 1
+                        || !group.statements.statements.back().is_some_and(Statement::is_abstraction)
+                }
+                _ => false,
+            };
+            if !flatten {
+                return;
             }
-            return ControlFlow::Running;
-        } else {
-            // If it does not have input, it is done running
-            parent_group.statements.statements.push_front(Statement::Abstraction(abstraction.clone()));
-            return ControlFlow::Finished;
+            if let Some(Statement::Group(group)) = statements.statements.pop_front() {
+                Self::push_statements_front(statements, &group.statements);
+            }
+        }
+    }
+    /// Applies the abstraction at the front of the statements to the statement after it.
+    fn application(statements: &mut Statements) -> ControlFlow {
+        let Some(Statement::Group(mut group)) = statements.statements.pop_front() else {
+            unreachable!("applications always start with a group");
         };
+        let Some(Statement::Abstraction(mut abstraction)) = group.statements.statements.pop_front() else {
+            unreachable!("the group of an application holds an abstraction");
+        };
+        let input = statements.statements.pop_front().expect("an application needs input");
+        Self::replace_names(&mut abstraction.next, &abstraction.variable, &input);
+        // If the body ends in an abstraction it needs to stay in a group,
+        // otherwise it would take the rest of the statements as its body.
+        if !statements.statements.is_empty() && abstraction.next.statements.back().is_some_and(Statement::is_abstraction) {
+            let span = abstraction.next.span.clone();
+            statements.statements.push_front(Statement::Group(Group { statements: abstraction.next, span }));
+        } else {
+            Self::push_statements_front(statements, &abstraction.next);
+        }
+        ControlFlow::Running
     }
-    /// Assumes it is running within a group. This will apply a lambda abstraction
-    /// that is within a group.
-    fn application(parent_statements: &mut Statements, group: &mut Group) -> ControlFlow {
-        let input = parent_statements.statements.pop_front();
-        let front = group.statements.statements.pop_front().expect("A group cannot be empty");
-        let ret = match front {
-            Statement::Abstraction(mut abstraction) => {
-                Self::apply_abstraction(group, &mut abstraction, input)
+    fn step_statement(statement: &mut Statement, strategy: Strategy) -> ControlFlow {
+        match statement {
+            Statement::Abstraction(abstraction) => {
+                if strategy == Strategy::CallByValue {
+                    return ControlFlow::Finished;
+                }
+                Self::step_statements(&mut abstraction.next, strategy)
             }
-            Statement::Group(mut g) => {
-                // If it has another group inside, execute internally
-                Self::application(&mut group.statements, &mut g);
-                ControlFlow::Running
+            // Types are erased before evaluation, but a type abstraction would
+            // be a value just like an abstraction
+            Statement::TypeAbstraction(abstraction) => {
+                if strategy == Strategy::CallByValue {
+                    return ControlFlow::Finished;
+                }
+                Self::step_statements(&mut abstraction.next, strategy)
             }
-            Statement::Term(t) => {
-                // If it has a single term inside, you are done
-                group.statements.statements.push_front(Statement::Term(t));
-                if let Some(input) = input {
-                    group.statements.statements.push_front(input);
+            Statement::Group(group) => Self::step_statements(&mut group.statements, strategy),
+            // A hole is stuck, it's a neutral term like a free variable
+            Statement::Term(_) | Statement::TypeApplication(_) | Statement::Hole(_) => ControlFlow::Finished,
+            Statement::Case(_) => unreachable!("cases are desugared before evaluation"),
+            Statement::Macro(_) => unreachable!("macros are expanded before evaluation"),
+        }
+    }
+    /// Steps through the inputs of a term that can't be applied.
+    fn step_inputs(statements: &mut Statements, strategy: Strategy) -> ControlFlow {
+        for statement in statements.statements.iter_mut().skip(1) {
+            if Self::step_statement(statement, strategy).is_running() {
+                return ControlFlow::Running;
+            }
+        }
+        ControlFlow::Finished
+    }
+    fn step_statements(statements: &mut Statements, strategy: Strategy) -> ControlFlow {
+        Self::flatten_front(statements);
+        let is_redex = match statements.statements.front_mut() {
+            None | Some(Statement::Term(_)) | Some(Statement::TypeApplication(_)) | Some(Statement::Case(_)) | Some(Statement::Hole(_)) | Some(Statement::Macro(_)) => false,
+            // An abstraction at the front has no input, because it extends to the end
+            Some(statement @ (Statement::Abstraction(_) | Statement::TypeAbstraction(_))) => return Self::step_statement(statement, strategy),
+            Some(Statement::Group(group)) => {
+                if group.statements.statements.len() > 1 {
+                    // Something like (f λx.x) a, so the group has to be reduced first
+                    if Self::step_statements(&mut group.statements, strategy).is_running() {
+                        return ControlFlow::Running;
+                    }
+                    false
+                } else {
+                    // A type abstraction can't take a term as its input
+                    matches!(group.statements.statements.front(), Some(Statement::Abstraction(_)))
                 }
-                ControlFlow::Finished
             }
         };
-        // Remove parenthesis by pushing the statements of group onto its parent.
-        // This effectively marks the end of the application of the lambda abstract.
-        Self::push_statements_front(parent_statements, &group.statements);
-        ret
-    }
-    // In the case of nested lambda abstracts that can still be executed.
-    fn currying_step(abstraction: &mut Abstraction) -> ControlFlow {
-        if let Some(next) = abstraction.next_curried_mut() {
-            let pop = next.next.statements.pop_front().unwrap();
-            if let Statement::Group(mut group) = pop.clone() {
-                Self::application(&mut next.next, &mut group);
+        if !is_redex {
+            return Self::step_inputs(statements, strategy);
+        }
+        if strategy == Strategy::CallByValue {
+            let input = statements.statements.iter_mut().nth(1).expect("a group is only kept at the front if it has input");
+            if Self::step_statement(input, strategy).is_running() {
                 return ControlFlow::Running;
             }
-            next.next.statements.push_front(pop);
-            return Self::currying_step(next);
         }
-        ControlFlow::Finished
+        Self::application(statements)
     }
-    
+
     /// Processes a single step of the program.
     pub fn step(&mut self) -> ControlFlow {
-        let statement = if let Some(s) = self.statements.statements.pop_front() {
-            s
-        } else { // If the program contains nothing, then it is finished running
-            return ControlFlow::Finished;
-        };
-        match statement {
-            Statement::Abstraction(mut abstraction) => {
-                let ret = Self::currying_step(&mut abstraction);
-                self.statements.statements.push_front(Statement::Abstraction(abstraction));
-                return ret;
-            }
-            Statement::Term(_) => {
-                self.statements.statements.push_front(statement);
-                return ControlFlow::Finished;
-            }
-            Statement::Group(mut group) => {
-                Self::application(&mut self.statements, &mut group);
-                return ControlFlow::Running;
+        Self::step_statements(&mut self.statements, self.strategy)
+    }
+    /// Steps the statements until nothing reduces, None if that takes more than `limit` steps.
+    pub fn normalize(statements: Statements, strategy: Strategy, limit: usize) -> Option<Statements> {
+        let mut state = Self::new(statements, strategy);
+        for _ in 0..limit {
+            if !state.step().is_running() {
+                return Some(state.statements);
             }
         }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    /// The normal form of the text, None if it takes more than a hundred steps.
+    fn normalize(text: &str, strategy: Strategy) -> Option<Statements> {
+        CalculatorState::normalize(Calculator::empty().parse(text).unwrap(), strategy, 100)
+    }
+
+    fn parse(text: &str) -> Statements {
+        Calculator::empty().parse(text).unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn substitution_avoids_capture() {
+        let reduced = normalize("(\\x. \\y. x) y", Strategy::NormalOrder).unwrap();
+        assert!(reduced.alpha_equivalent(&parse("\\z. y")));
+        assert!(!reduced.alpha_equivalent(&parse("\\y. y")));
+    }
+
+    #[test]
+    fn application_of_an_application_is_flattened() {
+        let reduced = normalize("((\\x. x) a) b", Strategy::NormalOrder).unwrap();
+        assert!(reduced.alpha_equivalent(&parse("a b")));
+    }
+
+    #[test]
+    fn normal_order_drops_a_diverging_argument() {
+        let text = "(\\x. z) ((\\x. x x) (\\x. x x))";
+        assert!(normalize(text, Strategy::NormalOrder).unwrap().alpha_equivalent(&parse("z")));
+        assert!(normalize(text, Strategy::CallByValue).is_none());
+    }
+
+    #[test]
+    fn call_by_value_stops_at_abstractions() {
+        let reduced = normalize("\\y. (\\x. x) y", Strategy::CallByValue).unwrap();
+        assert!(reduced.alpha_equivalent(&parse("\\y. (\\x. x) y")));
+        let reduced = normalize("\\y. (\\x. x) y", Strategy::NormalOrder).unwrap();
+        assert!(reduced.alpha_equivalent(&parse("\\y. y")));
+    }
+}
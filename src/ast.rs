@@ -1,39 +1,68 @@
-use std::{collections::LinkedList, fmt::Display};
+use std::{collections::{HashMap, HashSet, LinkedList}, fmt::Display};
 
-use gelato_parser::lexer::{ident::Ident, punct::Punct, span::Span, tokens::{Token, Tokens}};
+use gelato_parser::lexer::{Lexer, delimiter::Delimiter, ident::Ident, literal::LitKind, span::Span, tokens::{Token, Tokens}};
 
-use crate::error::ASTError;
+use crate::{data::DataType, error::ASTError, expansion::{Definition, SourceMap, macro_name, module_of}, operators::{Associativity, Operators}, printer::PrintOptions, types::Type};
 
 pub trait FromTokens: Sized {
-    fn from_tokens(tokens: &mut Tokens) -> Result<Self, ASTError>;
+    /// `operators` are the infix operators that have been declared.
+    fn from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError>;
 }
 
 /// λvariable.next
 #[derive(Debug, Clone)]
 pub struct Abstraction {
     pub variable: Term,
+    /// The type of the variable, in `λx:Nat.next`.
+    pub annotation: Option<Type>,
     pub next: Statements,
+    pub span: Span,
 }
-impl Abstraction {
-    pub fn next_curried(&self) -> Option<&Abstraction> {
-        if let Statement::Abstraction(abs) = self.next.statements.front().unwrap() {
-            Some(abs)
-        } else {
-            None
-        }
-    }
-    pub fn next_curried_mut(&mut self) -> Option<&mut Abstraction> {
-        if let Statement::Abstraction(abs) = self.next.statements.front_mut().unwrap() {
-            Some(abs)
-        } else {
-            None
-        }
-    }
+/// Λvariable.next, a term that takes a type as its input.
+#[derive(Debug, Clone)]
+pub struct TypeAbstraction {
+    pub variable: Ident,
+    pub next: Statements,
+    pub span: Span,
 }
-impl Display for Abstraction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("λ{}.{}", self.variable, self.next))
-    }
+/// [ty], gives a type to the term before it.
+#[derive(Debug, Clone)]
+pub struct TypeApplication {
+    pub ty: Type,
+    pub span: Span,
+}
+/// case scrutinee of { arms }, which desugars into the case
+/// analysis of the data type the constructors belong to.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub scrutinee: Statements,
+    pub arms: Vec<Arm>,
+    pub span: Span,
+}
+/// Constructor bindings... -> body, or _ -> body for every constructor left.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub constructor: Ident,
+    pub bindings: Vec<Term>,
+    pub body: Statements,
+    pub span: Span,
+}
+/// #name or #name(arguments...), a use of a macro. It gets replaced with the
+/// definition of the macro before the statements are checked or run.
+#[derive(Debug, Clone)]
+pub struct Macro {
+    /// The name with its '#'.
+    pub name: String,
+    /// The statements between the parenthesis right after the name, separated by commas.
+    pub arguments: Vec<Statements>,
+    pub span: Span,
+}
+/// ?name, a part of a term that hasn't been written yet.
+#[derive(Debug, Clone)]
+pub struct Hole {
+    /// Empty for a hole without a name, `?`.
+    pub name: String,
+    pub span: Span,
 }
 /// term
 #[derive(Debug, Clone, PartialEq)]
@@ -49,152 +78,1005 @@ impl Display for Term {
 #[derive(Debug, Clone)]
 pub struct Group {
     pub statements: Statements,
+    pub span: Span,
 }
-impl Group {
-    pub fn only_contains_terms(&self) -> bool {
-        for statement in self.statements.statements.iter() {
-            if statement.is_abstraction() {
-                return false;
-            }
-        }
-        true
-    }
-}
-impl Display for Group {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("({})", self.statements))
-    }
-}
-/// S ::= λ<term>.<optional-statement> | 
+/// S ::= λ<term>[:<type>]...<term>[:<type>].<optional-statement> | 
+/// Λ<ident>...<ident>.<statements> |
 /// <term> = string | 
-/// (S ...) 
+/// (S ...) |
+/// [<type>] |
+/// ?[<ident>] |
+/// #<ident>[::<ident>...][(S..., ...)] |
+/// case S... of { <ident> <term>... -> S...; ... }
 #[derive(Debug, Clone)]
 pub enum Statement {
     Abstraction(Abstraction),
     Group(Group),
-    Term(Term)
+    Term(Term),
+    TypeAbstraction(TypeAbstraction),
+    /// Only ever an input, never the head of an application.
+    TypeApplication(TypeApplication),
+    /// Desugared before the statements get checked or run.
+    Case(Case),
+    /// Evaluates like a free variable, nothing can be substituted into it.
+    Hole(Hole),
+    /// Expanded before the statements get checked or run.
+    Macro(Macro),
 }
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&PrintOptions::default().statement(self))
+    }
+}
+impl Statement {
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::Abstraction(abstraction) => &abstraction.span,
+            Statement::Group(group) => &group.span,
+            Statement::Term(term) => &term.name.span,
+            Statement::TypeAbstraction(abstraction) => &abstraction.span,
+            Statement::TypeApplication(application) => &application.span,
+            Statement::Case(case) => &case.span,
+            Statement::Hole(hole) => &hole.span,
+            Statement::Macro(reference) => &reference.span,
+        }
+    }
+    /// Builds the church numeral `λf.λx.f (f ... x)` for `n`.
+    pub fn church_numeral(n: usize, span: Span) -> Self {
+        let name = |ident: &str| Term { name: Ident { span: span.clone(), ident: ident.to_string() } };
+        let statements = |statements: LinkedList<Statement>| Statements { statements, span: span.clone() };
+        let mut body = statements(LinkedList::from([Statement::Term(name("x"))]));
+        for _ in 0..n {
+            let inner = if body.statements.len() == 1 {
+                body.statements.pop_front().unwrap()
+            } else {
+                Statement::Group(Group { statements: body, span: span.clone() })
+            };
+            body = statements(LinkedList::from([Statement::Term(name("f")), inner]));
+        }
+        let x = Statement::Abstraction(Abstraction { variable: name("x"), annotation: None, next: body, span: span.clone() });
+        let f = Statement::Abstraction(Abstraction { variable: name("f"), annotation: None, next: statements(LinkedList::from([x])), span: span.clone() });
+        Statement::Group(Group { statements: statements(LinkedList::from([f])), span })
+    }
+    pub fn free_variables(&self) -> HashSet<String> {
+        let mut free = HashSet::new();
+        self.collect_free_variables(&mut Vec::new(), &mut free);
+        free
+    }
+    fn collect_free_variables(&self, bound: &mut Vec<String>, free: &mut HashSet<String>) {
         match self {
             Statement::Abstraction(abstraction) => {
-                abstraction.fmt(f)
+                bound.push(abstraction.variable.name.ident.clone());
+                abstraction.next.collect_free_variables(bound, free);
+                bound.pop();
             }
             Statement::Group(group) => {
-                group.fmt(f)
+                group.statements.collect_free_variables(bound, free);
             }
             Statement::Term(term) => {
-                term.fmt(f)
+                if !bound.contains(&term.name.ident) {
+                    free.insert(term.name.ident.clone());
+                }
+            }
+            Statement::TypeAbstraction(abstraction) => {
+                abstraction.next.collect_free_variables(bound, free);
+            }
+            Statement::TypeApplication(_) | Statement::Hole(_) | Statement::Macro(_) => {}
+            Statement::Case(case) => {
+                case.scrutinee.collect_free_variables(bound, free);
+                for arm in case.arms.iter() {
+                    bound.extend(arm.bindings.iter().map(|binding| binding.name.ident.clone()));
+                    arm.body.collect_free_variables(bound, free);
+                    bound.truncate(bound.len() - arm.bindings.len());
+                }
             }
         }
     }
-}
-impl Statement {
-    pub fn is_abstraction(&self) -> bool {
-        if let Self::Abstraction(_) = self {
-            true
-        } else {
-            false
+    fn collect_names(&self, names: &mut HashSet<String>) {
+        match self {
+            Statement::Abstraction(abstraction) => {
+                names.insert(abstraction.variable.name.ident.clone());
+                abstraction.next.collect_names(names);
+            }
+            Statement::Group(group) => group.statements.collect_names(names),
+            Statement::Term(term) => {
+                names.insert(term.name.ident.clone());
+            }
+            Statement::TypeAbstraction(abstraction) => abstraction.next.collect_names(names),
+            Statement::TypeApplication(_) | Statement::Hole(_) | Statement::Macro(_) => {}
+            Statement::Case(case) => {
+                case.scrutinee.collect_names(names);
+                for arm in case.arms.iter() {
+                    names.extend(arm.bindings.iter().map(|binding| binding.name.ident.clone()));
+                    arm.body.collect_names(names);
+                }
+            }
         }
     }
-    pub fn is_group(&self) -> bool {
-        if let Self::Group(_) = self {
-            true
-        } else {
-            false
+    /// Parses what comes after the `case` keyword.
+    fn case_from_tokens(keyword: Ident, tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        // The scrutinee ends at its `of`, nested cases have their own
+        let mut scrutinee = vec![];
+        let mut nested = 0;
+        let of = loop {
+            let end = Span::new(keyword.span.range.end..keyword.span.range.end);
+            let token = tokens.next().ok_or(ASTError::Syntax("Expected 'of'".to_string(), keyword.span.join(&end)))?;
+            match token.get_ident() {
+                Some(ident) if ident.ident == "of" && nested == 0 => break ident,
+                Some(ident) if ident.ident == "of" => nested -= 1,
+                Some(ident) if ident.ident == "case" => nested += 1,
+                _ => {}
+            }
+            scrutinee.push(token);
+        };
+        let scrutinee = Statements::from_tokens(&mut Tokens { tokens: scrutinee, next: 0 }, operators)?;
+        if scrutinee.statements.is_empty() {
+            return Err(ASTError::Syntax("Expected a term to match on".to_string(), keyword.span.join(&of.span)));
+        }
+        let group = match tokens.next() {
+            Some(Token::Group(group)) if group.open == '{' && group.close != '\0' => group,
+            Some(Token::Group(group)) if group.open == '{' => {
+                return Err(ASTError::Syntax("Unclosed '{'".to_string(), group.span));
+            }
+            _ => return Err(ASTError::Syntax("Expected '{' after 'of'".to_string(), of.span)),
+        };
+        let span = keyword.span.join(&group.span);
+        let mut arms = vec![];
+        let mut inner = group.tokens;
+        while inner.next < inner.tokens.len() {
+            let constructor = inner.next().unwrap();
+            let Some(constructor) = constructor.get_ident() else {
+                return Err(ASTError::Syntax("Expected a constructor".to_string(), constructor.span().clone()));
+            };
+            let mut bindings = vec![];
+            while !Type::next_is_arrow(&mut inner) {
+                let end = Span::new(constructor.span.range.end..constructor.span.range.end);
+                let token = inner.next().ok_or(ASTError::Syntax("Expected '->'".to_string(), end))?;
+                let Some(binding) = token.get_ident() else {
+                    return Err(ASTError::Syntax("Expected a variable or '->'".to_string(), token.span().clone()));
+                };
+                bindings.push(Term { name: binding });
+            }
+            // The body goes up to the ';' that ends the arm
+            let start = inner.next;
+            while inner.tokens.get(inner.next).is_some_and(|token| !token.is_punct_subset(";")) {
+                inner.next += 1;
+            }
+            let body = inner.tokens[start..inner.next].to_vec();
+            inner.next += 1;
+            let body = Statements::from_tokens(&mut Tokens { tokens: body, next: 0 }, operators)?;
+            if body.statements.is_empty() {
+                return Err(ASTError::Syntax(format!("'{}' needs a body", constructor.ident), constructor.span.join(&body.span)));
+            }
+            let span = constructor.span.join(&body.span);
+            arms.push(Arm { constructor, bindings, body, span });
+        }
+        if arms.is_empty() {
+            return Err(ASTError::Syntax("A case needs at least one arm".to_string(), span));
         }
+        Ok(Self::Case(Case { scrutinee, arms, span }))
     }
-    pub fn is_term(&self) -> bool {
-        if let Self::Term(_) = self {
-            true
-        } else {
-            false
+    /// Parses the arguments of a macro, `(a, b c)` are the arguments `a` and `b c`.
+    fn arguments_from_tokens(group: &Delimiter, operators: &Operators) -> Result<Vec<Statements>, ASTError> {
+        if group.close == '\0' {
+            return Err(ASTError::Syntax("Unclosed '('".to_string(), group.span.clone()));
+        }
+        if group.tokens.tokens.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut arguments = vec![];
+        for tokens in group.tokens.tokens.split(|token| token.is_punct_subset(",")) {
+            let argument = Statements::from_tokens(&mut Tokens { tokens: tokens.to_vec(), next: 0 }, operators)?;
+            if argument.statements.is_empty() {
+                return Err(ASTError::Syntax("Expected an argument".to_string(), group.span.clone()));
+            }
+            arguments.push(argument);
         }
+        Ok(arguments)
+    }
+    /// Either kind of abstraction, both extend to the end of their statements.
+    pub fn is_abstraction(&self) -> bool {
+        matches!(self, Self::Abstraction(_) | Self::TypeAbstraction(_))
     }
 }
 impl FromTokens for Statement {
-    fn from_tokens(tokens: &mut Tokens) -> Result<Self, ASTError> {
-        match tokens.next().ok_or(ASTError::EmptyTokenList)? {
+    fn from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        let token = tokens.next().ok_or(ASTError::EmptyTokenList)?;
+        let span = token.span().clone();
+        match token {
             Token::Punct(punct) => { // Creating an Abstraction
                 if punct.punct.as_str() == "\\" {
-                    let variable = tokens
-                        .next().ok_or(ASTError::Syntax("Expected Variable".to_string()))?
-                        .get_ident().ok_or(ASTError::Syntax("Expected Identifier".to_string()))?;
-                    if !tokens
-                        .next().ok_or(ASTError::Syntax("Expected Punt".to_string()))?
-                        .get_punct().ok_or(ASTError::Syntax("Expected Punct".to_string()))?.is_punct(".") {
+                    // \x y z.next is short for \x.\y.\z.next
+                    let mut variables = vec![];
+                    loop {
+                        let end = Span::new(span.range.end..span.range.end);
+                        let token = tokens.next().ok_or(ASTError::Syntax("Expected Variable".to_string(), end))?;
+                        if token.is_punct_subset(".") && !variables.is_empty() {
+                            break;
+                        }
+                        if let Some(variable) = token.get_ident() {
+                            let annotation = if tokens.tokens.get(tokens.next).is_some_and(|token| token.is_punct_subset(":")) {
+                                tokens.next += 1;
+                                Some(Type::annotation_from_tokens(tokens, operators).map_err(|err| match err {
+                                    ASTError::EmptyTokenList => ASTError::Syntax("Expected a type".to_string(), variable.span.clone()),
+                                    err => err,
+                                })?)
+                            } else {
+                                None
+                            };
+                            variables.push((variable, annotation));
+                            continue;
+                        }
+                        if variables.is_empty() {
+                            return Err(ASTError::Syntax("Expected Identifier".to_string(), token.span().clone()));
+                        }
                         // check if syntax is ok
-                        return Err(ASTError::Syntax("Failed to locate '.'".to_string()));
+                        return Err(ASTError::Syntax("Failed to locate '.'".to_string(), token.span().clone()));
+                    }
+                    let mut next = Statements::from_tokens(tokens, operators)?;
+                    if next.statements.is_empty() {
+                        return Err(ASTError::Syntax("There needs to be statements after an abstraction".to_string(), span.join(&next.span)));
                     }
-                    let next = Statements::from_tokens(tokens)?;
+                    while let Some((variable, annotation)) = variables.pop() {
+                        let start = if variables.is_empty() { &span } else { &variable.span };
+                        let span = start.join(&next.span);
+                        let abstraction = Statement::Abstraction(Abstraction {
+                            variable: Term { name: variable },
+                            annotation,
+                            next,
+                            span: span.clone(),
+                        });
+                        next = Statements { statements: LinkedList::from([abstraction]), span };
+                    }
+                    Ok(next.statements.pop_front().unwrap())
+                } else if punct.punct.as_str() == "/" && tokens.tokens.get(tokens.next).is_some_and(|token| token.is_punct_subset("\\")) {
+                    // /\a b.next is short for /\a./\b.next
+                    tokens.next += 1;
+                    let mut variables = vec![];
+                    loop {
+                        let end = Span::new(span.range.end..span.range.end);
+                        let token = tokens.next().ok_or(ASTError::Syntax("Expected a type variable".to_string(), end))?;
+                        if token.is_punct_subset(".") && !variables.is_empty() {
+                            break;
+                        }
+                        let Some(variable) = token.get_ident() else {
+                            return Err(ASTError::Syntax("Expected a type variable".to_string(), token.span().clone()));
+                        };
+                        variables.push(variable);
+                    }
+                    let mut next = Statements::from_tokens(tokens, operators)?;
                     if next.statements.is_empty() {
-                        return Err(ASTError::Syntax("There needs to be statements after an abstraction".to_string()));
+                        return Err(ASTError::Syntax("There needs to be statements after a type abstraction".to_string(), span.join(&next.span)));
+                    }
+                    while let Some(variable) = variables.pop() {
+                        let start = if variables.is_empty() { &span } else { &variable.span };
+                        let span = start.join(&next.span);
+                        let abstraction = Statement::TypeAbstraction(TypeAbstraction { variable, next, span: span.clone() });
+                        next = Statements { statements: LinkedList::from([abstraction]), span };
+                    }
+                    Ok(next.statements.pop_front().unwrap())
+                } else if punct.punct.as_str() == "?" {
+                    // The name has to be right after the '?', `? x` is a hole applied to x
+                    let name = match tokens.tokens.get(tokens.next) {
+                        Some(Token::Ident(ident)) if ident.span.range.start == span.range.end => ident.clone(),
+                        _ => return Ok(Self::Hole(Hole { name: String::new(), span })),
+                    };
+                    tokens.next += 1;
+                    Ok(Self::Hole(Hole { name: name.ident, span: span.join(&name.span) }))
+                } else if punct.punct.as_str() == "#" {
+                    // The name has to be right after the '#'
+                    let Some((name, next, end)) = macro_name(&tokens.tokens, tokens.next, span.range.end) else {
+                        return Err(ASTError::Syntax("Expected the name of a macro".to_string(), span));
+                    };
+                    tokens.next = next;
+                    // The arguments are only the parenthesis that touch the name, #f (x) applies #f to x
+                    match tokens.tokens.get(tokens.next) {
+                        Some(Token::Group(group)) if group.open == '(' && group.span.range.start == end => {
+                            let group = group.clone();
+                            tokens.next += 1;
+                            let arguments = Self::arguments_from_tokens(&group, operators)?;
+                            Ok(Self::Macro(Macro { name, arguments, span: span.join(&group.span) }))
+                        }
+                        _ => Ok(Self::Macro(Macro { name, arguments: vec![], span: Span::new(span.range.start..end) })),
                     }
-                    return Ok(Statement::Abstraction(Abstraction { 
-                        variable: Term { name: variable }, 
-                        next,
-                    }));
-                } else { // No punct other than '\'
-                    println!("FAILED");
-                    return Err(ASTError::Syntax("Failed to find lambda".to_string()));
+                } else { // No punct other than '\', '/\', '?' and '#'
+                    Err(ASTError::Syntax("Failed to find lambda".to_string(), span))
                 }
             }
+            Token::Ident(ident) if ident.ident == "case" => Self::case_from_tokens(ident, tokens, operators),
             Token::Ident(ident) => {
-                return Ok(Self::Term(Term { name: ident }));
+                Ok(Self::Term(Term { name: ident }))
             }
             Token::Group(mut group) => {
-                // let mut tokens = group.tokens.iter().cloned();
-                return Ok(Self::Group(Group { statements: Statements::from_tokens(&mut group.tokens)? }));
+                if group.close == '\0' {
+                    return Err(ASTError::Syntax(format!("Unclosed '{}'", group.open), span));
+                }
+                if group.open == '[' {
+                    let ty = Type::from_tokens(&mut group.tokens, operators).map_err(|err| match err {
+                        ASTError::EmptyTokenList => ASTError::Syntax("Expected a type".to_string(), span.clone()),
+                        err => err,
+                    })?;
+                    if let Some(token) = group.tokens.next() {
+                        return Err(ASTError::Syntax("Expected the type to end".to_string(), token.span().clone()));
+                    }
+                    return Ok(Self::TypeApplication(TypeApplication { ty, span }));
+                }
+                Ok(Self::Group(Group { statements: Statements::from_tokens(&mut group.tokens, operators)?, span }))
             }
-            _ => return Err(ASTError::Syntax("Invalid Syntax".to_string())),
-        }
-    }
-}
-pub fn remove_multiple_whitespace(str: String) -> String {
-    let mut ret = String::new();
-    let mut whitespace_prev = false;
-    for c in str.chars() {
-        if c.is_whitespace() {
-            if !whitespace_prev {
-                ret.push(c);
+            Token::Literal(literal) if literal.kind == LitKind::Number => {
+                // numbers are sugar for church numerals
+                let n = literal.value.parse().map_err(|_| ASTError::Syntax(format!("Invalid number '{}'", literal.value), span))?;
+                Ok(Self::church_numeral(n, literal.span))
             }
-            whitespace_prev = true;
-        } else {
-            ret.push(c);
-            whitespace_prev = false;
+            _ => Err(ASTError::Syntax("Invalid Syntax".to_string(), span)),
         }
     }
-    ret
 }
 #[derive(Debug, Clone)]
 pub struct Statements {
     pub statements: LinkedList<Statement>,
+    pub span: Span,
 }
 impl Display for Statements {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut str = String::new();
-        
+        f.write_str(&PrintOptions::default().statements(self))
+    }
+}
+impl Statements {
+    /// Names of the variables that are not bound by an abstraction.
+    pub fn free_variables(&self) -> HashSet<String> {
+        let mut free = HashSet::new();
+        self.collect_free_variables(&mut Vec::new(), &mut free);
+        free
+    }
+    fn collect_free_variables(&self, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+        for statement in self.statements.iter() {
+            statement.collect_free_variables(bound, free);
+        }
+    }
+    /// Every variable name that appears, free or bound.
+    pub fn names(&self) -> HashSet<String> {
+        let mut names = HashSet::new();
+        self.collect_names(&mut names);
+        names
+    }
+    fn collect_names(&self, names: &mut HashSet<String>) {
+        for statement in self.statements.iter() {
+            statement.collect_names(names);
+        }
+    }
+    /// Renames the free occurrences of `from` to `to`.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Abstraction(abstraction) => {
+                    if abstraction.variable.name.ident != from {
+                        abstraction.next.rename(from, to);
+                    }
+                }
+                Statement::Group(group) => group.statements.rename(from, to),
+                Statement::Term(term) => {
+                    if term.name.ident == from {
+                        term.name.ident = to.to_string();
+                    }
+                }
+                Statement::TypeAbstraction(abstraction) => abstraction.next.rename(from, to),
+                Statement::TypeApplication(_) | Statement::Hole(_) | Statement::Macro(_) => {}
+                Statement::Case(case) => {
+                    case.scrutinee.rename(from, to);
+                    for arm in case.arms.iter_mut() {
+                        if !arm.bindings.iter().any(|binding| binding.name.ident == from) {
+                            arm.body.rename(from, to);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Renames the uses of the macro `from` to `to`, in the arguments of
+    /// other macros and in the types too.
+    pub fn rename_macro(&mut self, from: &str, to: &str) {
+        let rename_type = |ty: &mut Type| *ty = ty.instantiate(from, &Type::Base(to.to_string()));
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Macro(reference) => {
+                    if reference.name == from {
+                        reference.name = to.to_string();
+                    }
+                    for argument in reference.arguments.iter_mut() {
+                        argument.rename_macro(from, to);
+                    }
+                }
+                Statement::Abstraction(abstraction) => {
+                    if let Some(annotation) = abstraction.annotation.as_mut() {
+                        rename_type(annotation);
+                    }
+                    abstraction.next.rename_macro(from, to);
+                }
+                Statement::Group(group) => group.statements.rename_macro(from, to),
+                Statement::TypeAbstraction(abstraction) => abstraction.next.rename_macro(from, to),
+                Statement::TypeApplication(application) => rename_type(&mut application.ty),
+                Statement::Case(case) => {
+                    case.scrutinee.rename_macro(from, to);
+                    for arm in case.arms.iter_mut() {
+                        arm.body.rename_macro(from, to);
+                    }
+                }
+                Statement::Term(_) | Statement::Hole(_) => {}
+            }
+        }
+    }
+    /// Whether the statements are the same term up to the names of their bound
+    /// variables and their parenthesis, like `λx.x` and `λy.(y)`.
+    pub fn alpha_equivalent(&self, other: &Statements) -> bool {
+        alpha_equivalent(&spine(self), &spine(other), &mut Vec::new())
+    }
+    /// Whether the statements are a single abstraction, in parenthesis or not.
+    pub fn is_abstraction(&self) -> bool {
+        matches!(spine(self).as_slice(), [Statement::Abstraction(_)])
+    }
+    /// Replaces the parts of the statements that are alpha equivalent to one of the `forms`
+    /// with the name of its macro, the first form that matches wins.
+    pub fn fold_macros(&mut self, forms: &[(String, Statements)]) {
+        if let Some((name, _)) = forms.iter().find(|(_, form)| self.alpha_equivalent(form)) {
+            let reference = Macro { name: name.clone(), arguments: vec![], span: self.span.clone() };
+            self.statements = LinkedList::from([Statement::Macro(reference)]);
+            return;
+        }
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Group(group) => group.statements.fold_macros(forms),
+                Statement::Abstraction(abstraction) => {
+                    // The abstraction goes to the end, so it's a term of its own
+                    let term = Statements { statements: LinkedList::from([Statement::Abstraction(abstraction.clone())]), span: abstraction.span.clone() };
+                    match forms.iter().find(|(_, form)| term.alpha_equivalent(form)) {
+                        Some((name, _)) => {
+                            *statement = Statement::Macro(Macro { name: name.clone(), arguments: vec![], span: abstraction.span.clone() });
+                        }
+                        None => abstraction.next.fold_macros(forms),
+                    }
+                }
+                Statement::TypeAbstraction(abstraction) => abstraction.next.fold_macros(forms),
+                _ => {}
+            }
+        }
+    }
+    /// Gives every statement the same span, for terms that weren't written anywhere.
+    fn set_span(&mut self, span: &Span) {
+        self.map_spans(&|_| span.clone());
+    }
+    /// Moves every span of the statements over by `offset`.
+    pub fn shift_spans(&mut self, offset: usize) {
+        self.map_spans(&|span| Span::new(span.range.start + offset..span.range.end + offset));
+    }
+    fn map_spans(&mut self, f: &impl Fn(&Span) -> Span) {
+        self.span = f(&self.span);
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Abstraction(abstraction) => {
+                    abstraction.span = f(&abstraction.span);
+                    abstraction.variable.name.span = f(&abstraction.variable.name.span);
+                    abstraction.next.map_spans(f);
+                }
+                Statement::Group(group) => {
+                    group.span = f(&group.span);
+                    group.statements.map_spans(f);
+                }
+                Statement::Term(term) => term.name.span = f(&term.name.span),
+                Statement::TypeAbstraction(abstraction) => {
+                    abstraction.span = f(&abstraction.span);
+                    abstraction.variable.span = f(&abstraction.variable.span);
+                    abstraction.next.map_spans(f);
+                }
+                Statement::TypeApplication(application) => application.span = f(&application.span),
+                Statement::Hole(hole) => hole.span = f(&hole.span),
+                Statement::Macro(reference) => {
+                    reference.span = f(&reference.span);
+                    for argument in reference.arguments.iter_mut() {
+                        argument.map_spans(f);
+                    }
+                }
+                Statement::Case(case) => {
+                    case.span = f(&case.span);
+                    case.scrutinee.map_spans(f);
+                    for arm in case.arms.iter_mut() {
+                        arm.span = f(&arm.span);
+                        arm.constructor.span = f(&arm.constructor.span);
+                        for binding in arm.bindings.iter_mut() {
+                            binding.name.span = f(&binding.name.span);
+                        }
+                        arm.body.map_spans(f);
+                    }
+                }
+            }
+        }
+    }
+    /// Replaces every macro with the statements it is defined as, and the macros
+    /// those use in turn. Each expansion gets spans of its own after `end`, the end
+    /// of the spans that were written, and `map` records where they were expanded from.
+    pub fn expand_macros(&mut self, macros: &HashMap<String, Definition>, map: &mut SourceMap, end: usize) -> Result<(), ASTError> {
+        let mut end = end.max(map.end());
+        self.expand_macros_from(macros, map, &mut end, &mut vec![])?;
+        self.make_hygienic(map);
+        Ok(())
+    }
+    /// Renames the binders that would capture variables written somewhere else, like the binders of
+    /// a macro's body and the variables of the arguments it was given, or the binders around a use
+    /// of a macro and the free variables of its body. A variable is only bound by a binder that was
+    /// written in the same place, which is the text or the body of one expansion.
+    fn make_hygienic(&mut self, map: &SourceMap) {
+        let mut capturing = HashSet::new();
+        self.find_captures(map, &mut vec![], &mut 0, &mut capturing);
+        if capturing.is_empty() {
+            return;
+        }
+        let mut taken = self.names();
+        self.rename_captures(map, &mut vec![], &mut 0, &capturing, &mut taken);
+    }
+    /// `scope` holds the name, the expansion and the number of every binder around the statements,
+    /// the binders are numbered in the order they are found. The numbers of the binders that would
+    /// capture a variable they don't bind go into `capturing`.
+    fn find_captures(&self, map: &SourceMap, scope: &mut Vec<(String, Option<usize>, usize)>, count: &mut usize, capturing: &mut HashSet<usize>) {
         for statement in self.statements.iter() {
-            str.push_str(format!("{}", statement).as_str());
-            str.push(' ');
-            // statement.fmt(f)?;
+            match statement {
+                Statement::Term(term) => {
+                    let written_in = map.written_in(&term.name.span);
+                    let binder = scope.iter().rposition(|(name, from, _)| *name == term.name.ident && *from == written_in);
+                    let inner = binder.map_or(0, |i| i + 1);
+                    for (name, _, number) in scope[inner..].iter() {
+                        if *name == term.name.ident {
+                            capturing.insert(*number);
+                        }
+                    }
+                }
+                Statement::Abstraction(abstraction) => {
+                    scope.push((abstraction.variable.name.ident.clone(), map.written_in(&abstraction.variable.name.span), *count));
+                    *count += 1;
+                    abstraction.next.find_captures(map, scope, count, capturing);
+                    scope.pop();
+                }
+                Statement::Group(group) => group.statements.find_captures(map, scope, count, capturing),
+                Statement::TypeAbstraction(abstraction) => abstraction.next.find_captures(map, scope, count, capturing),
+                Statement::Case(case) => {
+                    case.scrutinee.find_captures(map, scope, count, capturing);
+                    for arm in case.arms.iter() {
+                        for binding in arm.bindings.iter() {
+                            scope.push((binding.name.ident.clone(), map.written_in(&binding.name.span), *count));
+                            *count += 1;
+                        }
+                        arm.body.find_captures(map, scope, count, capturing);
+                        scope.truncate(scope.len() - arm.bindings.len());
+                    }
+                }
+                Statement::TypeApplication(_) | Statement::Hole(_) | Statement::Macro(_) => {}
+            }
+        }
+    }
+    /// Gives the `capturing` binders names that aren't `taken`, along with the variables they bind.
+    /// `scope` holds the name, the expansion and the new name of every binder around the statements.
+    fn rename_captures(&mut self, map: &SourceMap, scope: &mut Vec<(String, Option<usize>, String)>, count: &mut usize, capturing: &HashSet<usize>, taken: &mut HashSet<String>) {
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Term(term) => {
+                    let written_in = map.written_in(&term.name.span);
+                    if let Some((_, _, renamed)) = scope.iter().rev().find(|(name, from, _)| *name == term.name.ident && *from == written_in) {
+                        term.name.ident = renamed.clone();
+                    }
+                }
+                Statement::Abstraction(abstraction) => {
+                    // Types can use the variables of the binders around them
+                    if let Some(annotation) = abstraction.annotation.as_mut() {
+                        for (name, _, renamed) in scope.iter().filter(|(name, _, renamed)| name != renamed) {
+                            *annotation = annotation.instantiate(name, &Type::Base(renamed.clone()));
+                        }
+                    }
+                    rename_binder(&mut abstraction.variable, map, scope, count, capturing, taken);
+                    abstraction.next.rename_captures(map, scope, count, capturing, taken);
+                    scope.pop();
+                }
+                Statement::Group(group) => group.statements.rename_captures(map, scope, count, capturing, taken),
+                Statement::TypeAbstraction(abstraction) => abstraction.next.rename_captures(map, scope, count, capturing, taken),
+                Statement::Case(case) => {
+                    case.scrutinee.rename_captures(map, scope, count, capturing, taken);
+                    for arm in case.arms.iter_mut() {
+                        for binding in arm.bindings.iter_mut() {
+                            rename_binder(binding, map, scope, count, capturing, taken);
+                        }
+                        arm.body.rename_captures(map, scope, count, capturing, taken);
+                        scope.truncate(scope.len() - arm.bindings.len());
+                    }
+                }
+                Statement::TypeApplication(_) | Statement::Hole(_) | Statement::Macro(_) => {}
+            }
+        }
+    }
+    /// `expanding` holds the macros whose bodies are being expanded, a macro inside of its own body would never end.
+    fn expand_macros_from(&mut self, macros: &HashMap<String, Definition>, map: &mut SourceMap, end: &mut usize, expanding: &mut Vec<String>) -> Result<(), ASTError> {
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Abstraction(abstraction) => {
+                    if let Some(annotation) = abstraction.annotation.as_mut() {
+                        *annotation = annotation.expand_macros(macros)
+                            .map_err(|msg| ASTError::Syntax(msg, abstraction.variable.name.span.clone()))?;
+                    }
+                    abstraction.next.expand_macros_from(macros, map, end, expanding)?
+                }
+                Statement::Group(group) => group.statements.expand_macros_from(macros, map, end, expanding)?,
+                Statement::TypeAbstraction(abstraction) => abstraction.next.expand_macros_from(macros, map, end, expanding)?,
+                Statement::TypeApplication(application) => {
+                    application.ty = application.ty.expand_macros(macros)
+                        .map_err(|msg| ASTError::Syntax(msg, application.span.clone()))?;
+                }
+                Statement::Term(_) | Statement::Hole(_) => {}
+                Statement::Case(case) => {
+                    case.scrutinee.expand_macros_from(macros, map, end, expanding)?;
+                    for arm in case.arms.iter_mut() {
+                        arm.body.expand_macros_from(macros, map, end, expanding)?;
+                    }
+                }
+                Statement::Macro(reference) => {
+                    for argument in reference.arguments.iter_mut() {
+                        argument.expand_macros_from(macros, map, end, expanding)?;
+                    }
+                    let Some(definition) = macros.get(&reference.name) else {
+                        return Err(ASTError::Syntax(format!("'{}' is not defined", reference.name), reference.span.clone()));
+                    };
+                    let Some(body) = definition.body.as_ref() else {
+                        return Err(ASTError::Syntax(format!("'{}' is a type, it can't be used as a term", reference.name), reference.span.clone()));
+                    };
+                    if expanding.contains(&reference.name) {
+                        return Err(ASTError::Syntax(format!("'{}' is defined in terms of itself", reference.name), reference.span.clone()));
+                    }
+                    let parameters = &definition.parameters;
+                    let arguments = std::mem::take(&mut reference.arguments);
+                    // A macro without parameters is applied to the parenthesis after it
+                    let applied = parameters.is_empty() && arguments.len() == 1;
+                    if !applied && arguments.len() != parameters.len() {
+                        let expected = match parameters.len() {
+                            1 => "1 argument".to_string(),
+                            count => format!("{count} arguments"),
+                        };
+                        let given = arguments.len();
+                        return Err(ASTError::Syntax(format!("'{}' takes {expected}, but was given {given}", reference.name), reference.span.clone()));
+                    }
+                    // The body gets spans of its own, inside of which the macros it uses are expanded
+                    let start = *end;
+                    *end += definition.len;
+                    let mut body = body.clone();
+                    body.shift_spans(start);
+                    let span = Span::new(start..start + definition.len);
+                    map.record(&reference.name, &reference.span, span.range.clone());
+                    if !applied {
+                        body.replace_parameters(&parameters.iter().cloned().zip(arguments.iter().cloned()).collect::<Vec<_>>());
+                    }
+                    expanding.push(reference.name.clone());
+                    body.expand_macros_from(macros, map, end, expanding)?;
+                    expanding.pop();
+                    *statement = match arguments.into_iter().next() {
+                        Some(argument) if applied => {
+                            let application = LinkedList::from([
+                                Statement::Group(Group { statements: body, span }),
+                                Statement::Group(Group { span: argument.span.clone(), statements: argument }),
+                            ]);
+                            let span = reference.span.clone();
+                            Statement::Group(Group { statements: Statements { statements: application, span: span.clone() }, span })
+                        }
+                        _ => Statement::Group(Group { statements: body, span }),
+                    };
+                }
+            }
         }
-        let _ = str.pop();
-        str = remove_multiple_whitespace(str);
-        f.write_str(format!("{}", str).as_str())?;
         Ok(())
     }
+    /// Replaces the uses of the parameters of a macro with the arguments it was given.
+    /// The arguments keep their spans, which point at where they were written.
+    fn replace_parameters(&mut self, arguments: &[(String, Statements)]) {
+        if arguments.is_empty() {
+            return;
+        }
+        let without = |names: &[&str]| -> Vec<(String, Statements)> {
+            arguments.iter().filter(|(parameter, _)| !names.contains(&parameter.as_str())).cloned().collect()
+        };
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Term(term) => {
+                    if let Some((_, argument)) = arguments.iter().find(|(parameter, _)| *parameter == term.name.ident) {
+                        *statement = Statement::Group(Group { statements: argument.clone(), span: argument.span.clone() });
+                    }
+                }
+                // A binder with the name of a parameter shadows it
+                Statement::Abstraction(abstraction) => abstraction.next.replace_parameters(&without(&[&abstraction.variable.name.ident])),
+                Statement::Group(group) => group.statements.replace_parameters(arguments),
+                Statement::TypeAbstraction(abstraction) => abstraction.next.replace_parameters(arguments),
+                Statement::TypeApplication(_) | Statement::Hole(_) => {}
+                Statement::Macro(reference) => {
+                    for argument in reference.arguments.iter_mut() {
+                        argument.replace_parameters(arguments);
+                    }
+                }
+                Statement::Case(case) => {
+                    case.scrutinee.replace_parameters(arguments);
+                    for arm in case.arms.iter_mut() {
+                        let bindings: Vec<&str> = arm.bindings.iter().map(|binding| binding.name.ident.as_str()).collect();
+                        arm.body.replace_parameters(&without(&bindings));
+                    }
+                }
+            }
+        }
+    }
+    /// Replaces every case with the case analysis of the data type its constructors belong to.
+    /// The constructors are looked up in the module of the macro the case was written in,
+    /// and in `module` for the text that was written.
+    pub fn desugar_cases(&mut self, data: &[DataType], map: &SourceMap, module: &str) -> Result<(), ASTError> {
+        for statement in self.statements.iter_mut() {
+            match statement {
+                Statement::Abstraction(abstraction) => abstraction.next.desugar_cases(data, map, module)?,
+                Statement::Group(group) => group.statements.desugar_cases(data, map, module)?,
+                Statement::TypeAbstraction(abstraction) => abstraction.next.desugar_cases(data, map, module)?,
+                Statement::Term(_) | Statement::TypeApplication(_) | Statement::Hole(_) => {}
+                Statement::Macro(_) => unreachable!("macros are expanded before cases are desugared"),
+                Statement::Case(case) => {
+                    case.scrutinee.desugar_cases(data, map, module)?;
+                    for arm in case.arms.iter_mut() {
+                        arm.body.desugar_cases(data, map, module)?;
+                    }
+                    let module = map.written_in(&case.span).map_or(module, |i| module_of(&map.expansions[i].name));
+                    *statement = Self::desugar_case(case, data, module)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn desugar_case(case: &Case, data: &[DataType], module: &str) -> Result<Statement, ASTError> {
+        let first = case.arms.iter().find(|arm| arm.constructor.ident != "_").unwrap_or(&case.arms[0]);
+        let Some(data_type) = DataType::of_constructor(data, &first.constructor.ident, module) else {
+            return Err(ASTError::Syntax(format!("'{}' isn't a constructor of a data type", first.constructor.ident), first.constructor.span.clone()));
+        };
+        let mut cases: Vec<Option<Statement>> = vec![None; data_type.constructors.len()];
+        let mut wildcard = None;
+        for arm in case.arms.iter() {
+            if arm.constructor.ident == "_" {
+                if wildcard.is_some() || cases.iter().all(Option::is_some) {
+                    return Err(ASTError::Syntax("This arm can't match anything".to_string(), arm.span.clone()));
+                }
+                if let Some(binding) = arm.bindings.first() {
+                    return Err(ASTError::Syntax("'_' doesn't have fields".to_string(), binding.name.span.clone()));
+                }
+                wildcard = Some(arm);
+                continue;
+            }
+            let Some(i) = data_type.constructors.iter().position(|constructor| constructor.name == arm.constructor.ident) else {
+                let msg = match DataType::of_constructor(data, &arm.constructor.ident, module) {
+                    Some(other) => format!("'{}' is a constructor of {}, not of {}", arm.constructor.ident, other.name, data_type.name),
+                    None => format!("'{}' isn't a constructor of {}", arm.constructor.ident, data_type.name),
+                };
+                return Err(ASTError::Syntax(msg, arm.constructor.span.clone()));
+            };
+            if cases[i].is_some() || wildcard.is_some() {
+                return Err(ASTError::Syntax("This arm can't match anything".to_string(), arm.span.clone()));
+            }
+            let fields = data_type.constructors[i].fields.len();
+            if arm.bindings.len() != fields {
+                return Err(ASTError::Syntax(format!("'{}' has {fields} fields, but {} were bound", arm.constructor.ident, arm.bindings.len()), arm.span.clone()));
+            }
+            cases[i] = Some(Self::abstract_over(&arm.bindings, &arm.body, &arm.span));
+        }
+        let mut missing = vec![];
+        for (case, constructor) in cases.iter_mut().zip(data_type.constructors.iter()) {
+            if case.is_some() {
+                continue;
+            }
+            let Some(arm) = wildcard else {
+                missing.push(constructor.name.as_str());
+                continue;
+            };
+            // The fields aren't used, but they can't capture anything in the body either
+            let mut taken = arm.body.free_variables();
+            let mut bindings = vec![];
+            for _ in constructor.fields.iter() {
+                let binding = fresh_name("_", &taken);
+                taken.insert(binding.clone());
+                bindings.push(Term { name: Ident { span: arm.constructor.span.clone(), ident: binding } });
+            }
+            *case = Some(Self::abstract_over(&bindings, &arm.body, &arm.span));
+        }
+        if !missing.is_empty() {
+            return Err(ASTError::Syntax(format!("The case doesn't match {}", missing.join(", ")), case.span.clone()));
+        }
+        let mut analysis = Statements::from_tokens(&mut Lexer::new(data_type.case_term()).parse(), &Operators::default())?;
+        analysis.set_span(&case.span);
+        let group = |statements: Statements| {
+            let span = statements.span.clone();
+            Statement::Group(Group { statements, span })
+        };
+        let mut statements = LinkedList::from([group(analysis), group(case.scrutinee.clone())]);
+        statements.extend(cases.into_iter().flatten());
+        Ok(group(Statements { statements, span: case.span.clone() }))
+    }
+    /// Builds `(λbindings.body)`, or `(body)` when there are no bindings.
+    fn abstract_over(bindings: &[Term], body: &Statements, span: &Span) -> Statement {
+        let mut next = body.clone();
+        for binding in bindings.iter().rev() {
+            let abstraction = Statement::Abstraction(Abstraction { variable: binding.clone(), annotation: None, next, span: span.clone() });
+            next = Statements { statements: LinkedList::from([abstraction]), span: span.clone() };
+        }
+        Statement::Group(Group { statements: next, span: span.clone() })
+    }
+    /// Removes the type abstractions, applications and annotations,
+    /// which don't change how a term evaluates.
+    pub fn erase_types(&mut self) {
+        for statement in std::mem::take(&mut self.statements) {
+            match statement {
+                Statement::TypeApplication(_) => {}
+                Statement::TypeAbstraction(abstraction) => {
+                    let mut next = abstraction.next;
+                    next.erase_types();
+                    if self.statements.is_empty() {
+                        self.statements = next.statements;
+                    } else {
+                        // It's an input, so its body has to stay together
+                        let span = next.span.clone();
+                        self.statements.push_back(Statement::Group(Group { statements: next, span }));
+                    }
+                }
+                Statement::Abstraction(mut abstraction) => {
+                    abstraction.annotation = None;
+                    abstraction.next.erase_types();
+                    self.statements.push_back(Statement::Abstraction(abstraction));
+                }
+                Statement::Group(mut group) => {
+                    group.statements.erase_types();
+                    self.statements.push_back(Statement::Group(group));
+                }
+                Statement::Term(term) => self.statements.push_back(Statement::Term(term)),
+                Statement::Hole(hole) => self.statements.push_back(Statement::Hole(hole)),
+                Statement::Macro(_) => unreachable!("macros are expanded before types are erased"),
+                Statement::Case(mut case) => {
+                    case.scrutinee.erase_types();
+                    for arm in case.arms.iter_mut() {
+                        arm.body.erase_types();
+                    }
+                    self.statements.push_back(Statement::Case(case));
+                }
+            }
+        }
+    }
+}
+/// The function of an application followed by its inputs, with the parenthesis
+/// around the function taken away, because `(f a) b` is `f a b`.
+fn spine(statements: &Statements) -> Vec<&Statement> {
+    let mut spine = vec![];
+    for (i, statement) in statements.statements.iter().enumerate() {
+        match unwrap_group(statement) {
+            Statement::Group(group) if i == 0 => spine.extend(self::spine(&group.statements)),
+            statement => spine.push(statement),
+        }
+    }
+    spine
+}
+/// The statement inside of parenthesis that only hold one statement.
+fn unwrap_group(mut statement: &Statement) -> &Statement {
+    while let Statement::Group(group) = statement && group.statements.statements.len() == 1 {
+        statement = group.statements.statements.front().unwrap();
+    }
+    statement
+}
+/// `bound` holds the variables bound around both sides, innermost last.
+fn alpha_equivalent(left: &[&Statement], right: &[&Statement], bound: &mut Vec<(String, String)>) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(left, right)| match (unwrap_group(left), unwrap_group(right)) {
+        (Statement::Term(left), Statement::Term(right)) => {
+            let left = &left.name.ident;
+            let right = &right.name.ident;
+            match (bound.iter().rposition(|(name, _)| name == left), bound.iter().rposition(|(_, name)| name == right)) {
+                (Some(i), Some(j)) => i == j,
+                (None, None) => left == right,
+                _ => false,
+            }
+        }
+        (Statement::Abstraction(left), Statement::Abstraction(right)) => {
+            if left.annotation.as_ref().map(|ty| ty.to_string()) != right.annotation.as_ref().map(|ty| ty.to_string()) {
+                return false;
+            }
+            bound.push((left.variable.name.ident.clone(), right.variable.name.ident.clone()));
+            let equivalent = alpha_equivalent(&spine(&left.next), &spine(&right.next), bound);
+            bound.pop();
+            equivalent
+        }
+        (Statement::Group(left), Statement::Group(right)) => {
+            alpha_equivalent(&spine(&left.statements), &spine(&right.statements), bound)
+        }
+        // Types are compared as they are written
+        (Statement::TypeAbstraction(left), Statement::TypeAbstraction(right)) => {
+            left.variable.ident == right.variable.ident && alpha_equivalent(&spine(&left.next), &spine(&right.next), bound)
+        }
+        (Statement::TypeApplication(left), Statement::TypeApplication(right)) => left.ty.to_string() == right.ty.to_string(),
+        (Statement::Hole(left), Statement::Hole(right)) => left.name == right.name,
+        (Statement::Macro(left), Statement::Macro(right)) => {
+            left.name == right.name && left.arguments.len() == right.arguments.len()
+                && left.arguments.iter().zip(right.arguments.iter())
+                    .all(|(left, right)| alpha_equivalent(&spine(left), &spine(right), bound))
+        }
+        _ => false,
+    })
+}
+/// Renames the binder if it's one of the `capturing` ones, and puts it in the scope.
+fn rename_binder(variable: &mut Term, map: &SourceMap, scope: &mut Vec<(String, Option<usize>, String)>, count: &mut usize, capturing: &HashSet<usize>, taken: &mut HashSet<String>) {
+    let name = variable.name.ident.clone();
+    if capturing.contains(count) {
+        variable.name.ident = fresh_name(&name, taken);
+        taken.insert(variable.name.ident.clone());
+    }
+    scope.push((name, map.written_in(&variable.name.span), variable.name.ident.clone()));
+    *count += 1;
+}
+/// Appends underscores to `base` until it is not one of the `taken` names.
+pub fn fresh_name(base: &str, taken: &HashSet<String>) -> String {
+    let mut name = base.to_string();
+    while taken.contains(&name) {
+        name.push('_');
+    }
+    name
 }
+/// Statements are parsed so that infix operators bind their operands
+/// by precedence and associativity.
 impl FromTokens for Statements {
-    fn from_tokens(tokens: &mut Tokens) -> Result<Self, ASTError> {
+    fn from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        Self::parse_operators(tokens, operators, 0)
+    }
+}
+impl Statements {
+    /// Precedence climbing, only operators with a precedence of at least `min` are taken.
+    fn parse_operators(tokens: &mut Tokens, operators: &Operators, min: u8) -> Result<Self, ASTError> {
+        let mut lhs = Self::parse_application(tokens, operators)?;
+        while let Some(operator) = operators.peek(tokens) {
+            if operator.precedence < min {
+                break;
+            }
+            let span = tokens.next().unwrap().span().clone();
+            if lhs.statements.is_empty() {
+                return Err(ASTError::Syntax(format!("'{}' is missing its left operand", operator.symbol), span));
+            }
+            let next = match operator.associativity {
+                Associativity::Right => operator.precedence,
+                _ => operator.precedence.saturating_add(1),
+            };
+            let rhs = Self::parse_operators(tokens, operators, next)?;
+            if rhs.statements.is_empty() {
+                return Err(ASTError::Syntax(format!("'{}' is missing its right operand", operator.symbol), span));
+            }
+            lhs = operator.apply(lhs, rhs, &span);
+            if operator.associativity == Associativity::None
+                && let Some(next) = operators.peek(tokens).filter(|next| next.precedence == operator.precedence) {
+                let span = tokens.tokens[tokens.next].span().clone();
+                return Err(ASTError::Syntax(format!("'{}' can't be chained with '{}'", next.symbol, operator.symbol), span));
+            }
+        }
+        Ok(lhs)
+    }
+    /// Parses statements up to the next operator.
+    fn parse_application(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
         let mut statements = LinkedList::new();
+        // Empty statements get an empty span right after the previous token
+        let start = match tokens.tokens.get(tokens.next) {
+            Some(token) => token.span().range.start,
+            None => tokens.next.checked_sub(1).map_or(0, |i| tokens.tokens[i].span().range.end),
+        };
         loop {
-            match Statement::from_tokens(tokens) {
+            let statement = if operators.peek(tokens).is_some() {
+                Err(ASTError::EmptyTokenList)
+            } else {
+                Statement::from_tokens(tokens, operators)
+            };
+            match statement {
+                Ok(Statement::TypeApplication(application)) if statements.is_empty() => {
+                    return Err(ASTError::Syntax("A type application needs a term before it".to_string(), application.span));
+                }
                 Ok(statement) => {
                     statements.push_back(statement);
                 }
                 Err(ASTError::EmptyTokenList) => {
-                    return Ok(Self { statements });
+                    let end = statements.back().map_or(start, |statement: &Statement| statement.span().range.end);
+                    return Ok(Self { statements, span: Span::new(start..end) });
                 }
                 Err(err) => {
                     return Err(err);
@@ -202,4 +1084,53 @@ impl FromTokens for Statements {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::app::{calculator::Calculator, recursion::macro_references};
+
+    use super::*;
+
+    fn parse(text: &str) -> Statements {
+        Calculator::empty().parse(text).unwrap()
+    }
+    fn define(macros: &mut HashMap<String, Definition>, name: &str, parameters: &[&str], source: &str) {
+        let definition = Definition {
+            source: source.to_string(),
+            parameters: parameters.iter().map(|parameter| parameter.to_string()).collect(),
+            references: macro_references(source),
+            body: Some(parse(source)),
+            ty: None,
+            len: source.len(),
+        };
+        macros.insert(name.to_string(), definition);
+    }
+    fn expand(macros: &HashMap<String, Definition>, text: &str) -> String {
+        let mut statements = parse(text);
+        statements.expand_macros(macros, &mut SourceMap::default(), text.len()).unwrap();
+        PrintOptions::default().statements(&statements)
+    }
+
+    #[test]
+    fn binder_of_a_macro_doesnt_capture_an_argument() {
+        let mut macros = HashMap::new();
+        define(&mut macros, "#twice", &["f"], "\\x. f (f x)");
+        assert_eq!(expand(&macros, "\\x. #twice(\\y. x y)"), "λx x_.(λy.x y) ((λy.x y) x_)");
+    }
+
+    #[test]
+    fn binder_written_outside_doesnt_capture_a_free_variable_of_a_macro() {
+        let mut macros = HashMap::new();
+        define(&mut macros, "#const", &[], "\\a. y");
+        assert_eq!(expand(&macros, "\\y. #const"), "λy_ a.y");
+    }
+
+    #[test]
+    fn binders_that_capture_nothing_keep_their_names() {
+        let mut macros = HashMap::new();
+        define(&mut macros, "#twice", &["f"], "\\x. f (f x)");
+        define(&mut macros, "#id", &[], "\\a. a");
+        assert_eq!(expand(&macros, "\\z. #twice(\\y. z y)"), "λz x.(λy.z y) ((λy.z y) x)");
+        assert_eq!(expand(&macros, "\\a. #id a"), "λa.(λa.a) a");
+    }
+}
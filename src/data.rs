@@ -0,0 +1,211 @@
+use std::collections::HashSet;
+
+use crate::{ast::fresh_name, types::variable_name};
+
+/// How the values of a data type are represented as functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Encoding {
+    /// A value is its own fold, recursive fields are already folded.
+    #[default]
+    Church,
+    /// A value is its own case analysis, recursive fields are left as they are.
+    Scott,
+    /// Both, every recursive field comes with its fold after it.
+    Parigot,
+}
+
+/// One field of a constructor, like the `(List a)` of `Cons a (List a)`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Field {
+    pub ty: String,
+    /// The field holds a value of the data type that is being declared.
+    pub recursive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Constructor {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+/// A data type declared with `data List a = Nil | Cons a (List a)`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DataType {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub constructors: Vec<Constructor>,
+    pub encoding: Encoding,
+    /// The module that declared it, empty outside of an import.
+    pub module: String,
+}
+
+impl DataType {
+    /// The name of the eliminator macro, `#elimList` for `List`.
+    pub fn eliminator(&self) -> String {
+        format!("#elim{}", self.name)
+    }
+    /// The name of the case analysis macro, `#caseList` for `List`.
+    pub fn case(&self) -> String {
+        format!("#case{}", self.name)
+    }
+    /// Names of the inputs a value takes, one for each constructor.
+    fn cases(&self) -> Vec<String> {
+        let mut taken = HashSet::new();
+        self.constructors.iter().map(|constructor| {
+            let case = fresh_name(&constructor.name.to_lowercase(), &taken);
+            taken.insert(case.clone());
+            case
+        }).collect()
+    }
+    /// Names for `count` variables that aren't one of the `taken` names.
+    fn variables(count: usize, taken: &HashSet<String>) -> Vec<String> {
+        (0..).map(variable_name).filter(|name| !taken.contains(name)).take(count).collect()
+    }
+    /// The term of the i'th constructor, like `\a b. \nil cons. cons a (b nil cons)`.
+    pub fn constructor(&self, i: usize) -> String {
+        let cases = self.cases();
+        let constructor = &self.constructors[i];
+        let fields = Self::variables(constructor.fields.len(), &cases.iter().cloned().collect());
+        let mut body = String::from("\\");
+        if !fields.is_empty() {
+            body.push_str(&fields.join(" "));
+            body.push_str(". \\");
+        }
+        body.push_str(&cases.join(" "));
+        body.push_str(". ");
+        body.push_str(&cases[i]);
+        for (field, name) in constructor.fields.iter().zip(fields.iter()) {
+            let fold = format!("({name} {})", cases.join(" "));
+            match (self.encoding, field.recursive) {
+                (Encoding::Church, true) => body.push_str(&format!(" {fold}")),
+                (Encoding::Parigot, true) => body.push_str(&format!(" {name} {fold}")),
+                _ => body.push_str(&format!(" {name}")),
+            }
+        }
+        body
+    }
+    /// A value already is its eliminator, this only fixes the order of the cases.
+    pub fn eliminator_term(&self) -> String {
+        let cases = self.cases();
+        let value = fresh_name("v", &cases.iter().cloned().collect());
+        format!("\\{value} {}. {value} {}", cases.join(" "), cases.join(" "))
+    }
+    /// Takes a value and one case for each constructor, which gets the fields of the
+    /// value as they are. Only Scott values are their own case analysis, a Church value
+    /// has to be rebuilt while it's folded, and a Parigot value drops the folds.
+    pub fn case_term(&self) -> String {
+        let cases = self.cases();
+        let mut taken: HashSet<String> = cases.iter().cloned().collect();
+        let value = fresh_name("v", &taken);
+        taken.insert(value.clone());
+        let mut steps = vec![];
+        for (i, (constructor, case)) in self.constructors.iter().zip(cases.iter()).enumerate() {
+            let fields = Self::variables(constructor.fields.len(), &taken);
+            let step = match self.encoding {
+                Encoding::Scott => case.clone(),
+                Encoding::Parigot => {
+                    // Every recursive field is followed by its fold, which gets ignored
+                    let mut inputs = vec![];
+                    let mut folds = taken.clone();
+                    folds.extend(fields.iter().cloned());
+                    for (field, name) in constructor.fields.iter().zip(fields.iter()) {
+                        inputs.push(name.clone());
+                        if field.recursive {
+                            let fold = fresh_name(&format!("{name}_fold"), &folds);
+                            folds.insert(fold.clone());
+                            inputs.push(fold);
+                        }
+                    }
+                    if inputs.is_empty() {
+                        case.clone()
+                    } else {
+                        format!("(\\{}. {case} {})", inputs.join(" "), fields.join(" "))
+                    }
+                }
+                Encoding::Church => {
+                    // The fold gives back a pair of the result of the case and the value
+                    // it was made from, the case gets the value of the recursive fields
+                    let mut bound = taken.clone();
+                    bound.extend(fields.iter().cloned());
+                    let pair = fresh_name("k", &bound);
+                    let inputs: Vec<String> = constructor.fields.iter().zip(fields.iter())
+                        .map(|(field, name)| if field.recursive { format!("({name} {SECOND})") } else { name.clone() })
+                        .collect();
+                    let applied = |function: &str| {
+                        if inputs.is_empty() {
+                            function.to_string()
+                        } else {
+                            format!("({function} {})", inputs.join(" "))
+                        }
+                    };
+                    let rebuilt = applied(&format!("({})", self.constructor(i)));
+                    let body = format!("\\{pair}. {pair} {} {rebuilt}", applied(case));
+                    if fields.is_empty() {
+                        format!("({body})")
+                    } else {
+                        format!("(\\{}. {body})", fields.join(" "))
+                    }
+                }
+            };
+            steps.push(step);
+        }
+        let applied = format!("{value} {}", steps.join(" "));
+        let body = match self.encoding {
+            Encoding::Church => format!("{applied} {FIRST}"),
+            _ => applied,
+        };
+        format!("\\{value} {}. {body}", cases.join(" "))
+    }
+    /// The constructor, eliminator and case macros of the data type, in the encoding it was declared with.
+    pub fn macros(&self) -> Vec<(String, String)> {
+        let mut macros: Vec<(String, String)> = self.constructors.iter().enumerate()
+            .map(|(i, constructor)| (format!("#{}", constructor.name), self.constructor(i)))
+            .collect();
+        macros.push((self.eliminator(), self.eliminator_term()));
+        macros.push((self.case(), self.case_term()));
+        macros
+    }
+    /// The data type that declares the constructor, the one of `module` if it has one.
+    pub fn of_constructor<'a>(data: &'a [DataType], name: &str, module: &str) -> Option<&'a DataType> {
+        let declares = |data: &&DataType| data.constructors.iter().any(|constructor| constructor.name == name);
+        data.iter().rev().filter(declares).find(|data| data.module == module)
+            .or_else(|| data.iter().rev().find(declares))
+    }
+}
+
+/// Takes the first of a pair `\k. k a b`.
+const FIRST: &str = "(\\a b. a)";
+/// Takes the second of a pair `\k. k a b`.
+const SECOND: &str = "(\\a b. b)";
+
+/// Splits the fields of a constructor at the spaces that aren't inside of parenthesis.
+pub fn split_fields(text: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or("Unopened ')'".to_string())?,
+            c if c.is_whitespace() && depth == 0 => {
+                if !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        field.push(c);
+    }
+    if depth > 0 {
+        return Err("Unclosed '('".to_string());
+    }
+    if !field.is_empty() {
+        fields.push(field);
+    }
+    Ok(fields)
+}
+/// Names of data types, their parameters and constructors are made of letters and '_'.
+pub fn is_name(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphabetic() || c == '_')
+}
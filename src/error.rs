@@ -1,10 +1,45 @@
+use gelato_parser::lexer::span::Span;
 use thiserror::Error;
 
+use crate::expansion::SourceMap;
+
 #[derive(Debug, Error, Clone)]
 pub enum ASTError {
     #[error("Syntax error: {0}")]
-    Syntax(String),
+    Syntax(String, Span),
+    #[error("Type error: {0}")]
+    Type(String, Span),
     // Used to mark the end of reading statements
     #[error("Finished Reading")]
     EmptyTokenList,
-}
\ No newline at end of file
+}
+
+impl ASTError {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            ASTError::Syntax(_, span) | ASTError::Type(_, span) => Some(span),
+            ASTError::EmptyTokenList => None,
+        }
+    }
+    /// Formats the error with the part of the source it points at underlined.
+    /// The span of the error is traced back through the macros in `map`.
+    pub fn report(&self, source: &str, map: &SourceMap) -> String {
+        let Some(span) = self.span() else {
+            return self.to_string();
+        };
+        let origin = map.origin(span);
+        let source = source.trim_end();
+        let start = origin.span.range.start.min(source.len());
+        // Only the line the span starts on is shown, for sources that have several
+        let line_start = source.get(..start).and_then(|before| before.rfind('\n')).map_or(0, |i| i + 1);
+        let line_end = source.get(start..).and_then(|after| after.find('\n')).map_or(source.len(), |i| start + i);
+        let len = origin.span.range.end.min(line_end).saturating_sub(start).max(1);
+        let indent = source.get(line_start..start).map_or(start - line_start, |before| before.chars().count());
+        let line = &source[line_start..line_end];
+        let mut report = format!("{self}\n | {line}\n | {}{}", " ".repeat(indent), "^".repeat(len));
+        for name in origin.expanded_from.iter() {
+            report.push_str(&format!("\n = in the expansion of {name}"));
+        }
+        report
+    }
+}
@@ -0,0 +1,180 @@
+use std::ops::Range;
+
+use gelato_parser::lexer::{literal::LitKind, span::Span, tokens::Token};
+
+use crate::{ast::Statements, types::Type};
+
+/// A macro that was defined, as the statements it stands for. The macros it
+/// uses are only looked up when it gets expanded, so they can be redefined.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    /// The text the macro was defined with.
+    pub source: String,
+    /// The names of the parameters, like the `f` of `#twice(f) = \x. f (f x)`.
+    pub parameters: Vec<String>,
+    /// The macros the text uses, whether they are defined or not.
+    pub references: Vec<String>,
+    /// Recursive macros are compiled with a fixed point combinator, so their body
+    /// doesn't use the macros of their group. None for macros like `#Nat = Pi A:Type. A`, that can only be read as a type.
+    pub body: Option<Statements>,
+    /// The definition read as a type, for the macros that are used inside of types.
+    pub ty: Option<Type>,
+    /// The length of the text that was parsed into the body, which is
+    /// how many offsets the spans of an expansion of it take up.
+    pub len: usize,
+}
+
+/// Reads the name of a macro from the tokens after its '#', which ends at `end`. A name is an
+/// identifier, or several joined by `::` like `bool::and`, all touching each other. Returns the
+/// name with its '#', the index of the token after it, and the offset it ends at.
+pub fn macro_name(tokens: &[Token], mut i: usize, end: usize) -> Option<(String, usize, usize)> {
+    let Some(Token::Ident(ident)) = tokens.get(i) else {
+        return None;
+    };
+    if ident.span.range.start != end {
+        return None;
+    }
+    let mut name = format!("#{}", ident.ident);
+    let mut end = ident.span.range.end;
+    i += 1;
+    (i, end) = name_rest(tokens, i, end, &mut name);
+    loop {
+        // `::` is a single punct when it's declared as an operator, and two otherwise
+        let (separator, next) = match (tokens.get(i), tokens.get(i + 1)) {
+            (Some(Token::Punct(punct)), _) if punct.punct == "::" && punct.span.range.start == end => (punct.span.range.end, i + 1),
+            (Some(Token::Punct(first)), Some(Token::Punct(second)))
+                if first.punct == ":" && second.punct == ":" && first.span.range.start == end && second.span.range.start == first.span.range.end => {
+                (second.span.range.end, i + 2)
+            }
+            _ => break,
+        };
+        match tokens.get(next) {
+            Some(Token::Ident(ident)) if ident.span.range.start == separator => {
+                name.push_str("::");
+                name.push_str(&ident.ident);
+                (i, end) = name_rest(tokens, next + 1, ident.span.range.end, &mut name);
+            }
+            _ => break,
+        }
+    }
+    Some((name, i, end))
+}
+/// The digits and letters that touch the start of a part of a name, which the lexer reads
+/// as numbers and identifiers of their own, so `#f2` is a name and not `#f` applied to 2.
+fn name_rest(tokens: &[Token], mut i: usize, mut end: usize, name: &mut String) -> (usize, usize) {
+    loop {
+        match tokens.get(i) {
+            Some(Token::Literal(literal)) if literal.kind == LitKind::Number && literal.span.range.start == end => {
+                name.push_str(&literal.value);
+                end = literal.span.range.end;
+            }
+            Some(Token::Ident(ident)) if ident.span.range.start == end => {
+                name.push_str(&ident.ident);
+                end = ident.span.range.end;
+            }
+            _ => return (i, end),
+        }
+        i += 1;
+    }
+}
+/// Whether the text can be the name of a macro, like `#and`, `#f2` or `#bool::and`.
+pub fn is_macro_name(text: &str) -> bool {
+    let is_part = |part: &str| part.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    text.strip_prefix('#').is_some_and(|name| name.split("::").all(is_part))
+}
+/// The module a macro belongs to, `bool` for `#bool::and`, and nothing for `#and`.
+pub fn module_of(name: &str) -> &str {
+    name.trim_start_matches('#').rsplit_once("::").map_or("", |(module, _)| module)
+}
+
+impl Definition {
+    /// The left side of the definition, like `#twice(f)`.
+    pub fn head(&self, name: &str) -> String {
+        if self.parameters.is_empty() {
+            return name.to_string();
+        }
+        format!("{name}({})", self.parameters.join(", "))
+    }
+}
+
+/// A single use of a macro that was replaced with its body.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub name: String,
+    /// The spans the body of the macro got. They come after the
+    /// spans of the text that was written, and of every expansion before.
+    pub range: Range<usize>,
+    /// Where the outermost macro that caused this expansion was
+    /// written in the original text.
+    pub call_site: Range<usize>,
+    /// The expansion whose body contained this macro.
+    pub parent: Option<usize>,
+}
+
+/// Where a span of the expanded statements came from in the original text.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub span: Span,
+    /// The macros the span was expanded from, innermost first.
+    pub expanded_from: Vec<String>,
+}
+
+/// Records the macros that were expanded in some statements, so the spans
+/// of their bodies can be traced back to the text that was written.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub expansions: Vec<Expansion>,
+}
+
+impl SourceMap {
+    /// The innermost expansion that contains the offset.
+    fn expansion_at(&self, offset: usize) -> Option<usize> {
+        self.expansions.iter().enumerate()
+            .filter(|(_, expansion)| expansion.range.contains(&offset))
+            .min_by_key(|(_, expansion)| expansion.range.len())
+            .map(|(i, _)| i)
+    }
+    /// The expansion the span was written in, None for the text that was written.
+    pub fn written_in(&self, span: &Span) -> Option<usize> {
+        self.expansion_at(span.range.start)
+    }
+    /// The end of the spans of the expansions, where the next one can start.
+    pub fn end(&self) -> usize {
+        self.expansions.iter().map(|expansion| expansion.range.end).max().unwrap_or(0)
+    }
+    /// Records that the macro `name` used at `call_site` was given the spans of `range`.
+    /// A call site inside of another expansion makes it a part of that expansion.
+    pub fn record(&mut self, name: &str, call_site: &Span, range: Range<usize>) {
+        let parent = self.expansion_at(call_site.range.start);
+        let call_site = match parent {
+            Some(parent) => self.expansions[parent].call_site.clone(),
+            None => call_site.range.clone(),
+        };
+        self.expansions.push(Expansion { name: name.to_string(), range, call_site, parent });
+    }
+    /// The expansion that covers exactly the span, which is
+    /// the body of a macro in the group it was expanded into.
+    pub fn expansion_of(&self, span: &Span) -> Option<&Expansion> {
+        self.expansions.iter().find(|expansion| expansion.range == span.range)
+    }
+    /// Traces a span back to the original text. Spans inside of a macro's
+    /// body point at where the macro was used.
+    pub fn origin(&self, span: &Span) -> Origin {
+        let start = self.expansion_at(span.range.start);
+        let end = self.expansion_at(span.range.end.saturating_sub(1).max(span.range.start));
+        if let Some(mut expansion) = start && start == end {
+            let span = Span::new(self.expansions[expansion].call_site.clone());
+            let mut expanded_from = vec![self.expansions[expansion].name.clone()];
+            while let Some(parent) = self.expansions[expansion].parent {
+                expanded_from.push(self.expansions[parent].name.clone());
+                expansion = parent;
+            }
+            return Origin { span, expanded_from };
+        }
+        // A span that reaches into an expansion covers all of the macro that was used
+        let start = start.map_or(span.range.start, |i| self.expansions[i].call_site.start);
+        let end = end.map_or(span.range.end, |i| self.expansions[i].call_site.end);
+        Origin { span: Span::new(start.min(end)..end.max(start)), expanded_from: vec![] }
+    }
+}
@@ -1,10 +1,29 @@
-use crate::{app::{App, calculator::Calculator}, ast::remove_multiple_whitespace, error::ASTError};
+use crate::app::App;
 
 mod ast;
 mod error;
+mod expansion;
+mod data;
+mod printer;
+mod readback;
+mod operators;
+mod prelude;
+mod types;
 mod app;
 fn main() {
     
     let mut app = App::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // `test a.lam b.lam` runs the assertions of the files instead of the REPL
+    if let Some(("test", paths)) = args.split_first().map(|(first, rest)| (first.as_str(), rest)) {
+        if !app.test(paths) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    // The files given as arguments are loaded before the REPL starts
+    for path in &args {
+        app.load_file(path);
+    }
     app.run();
 }
\ No newline at end of file
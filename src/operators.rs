@@ -0,0 +1,103 @@
+use std::collections::LinkedList;
+
+use gelato_parser::lexer::{span::Span, tokens::Tokens};
+
+use crate::ast::{Group, Statement, Statements};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Associativity {
+    /// `infixl`, a + b + c is (a + b) + c
+    Left,
+    /// `infixr`, a :: b :: c is a :: (b :: c)
+    Right,
+    /// `infix`, a == b == c is an error
+    None,
+}
+
+/// An infix operator declared with `infixl 6 + = #add`.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub symbol: String,
+    pub associativity: Associativity,
+    pub precedence: u8,
+    /// What `a <symbol> b` means, it gets applied to a and b.
+    pub term: Statements,
+}
+
+impl Operator {
+    /// Builds `term lhs rhs`.
+    pub fn apply(&self, lhs: Statements, rhs: Statements, span: &Span) -> Statements {
+        let operand = |statements: Statements| {
+            if statements.statements.len() == 1 && !statements.statements.front().unwrap().is_abstraction() {
+                statements.statements.into_iter().next().unwrap()
+            } else {
+                let span = statements.span.clone();
+                Statement::Group(Group { statements, span })
+            }
+        };
+        let term = Statement::Group(Group { statements: self.term.clone(), span: span.clone() });
+        let span = lhs.span.join(&rhs.span);
+        Statements { statements: LinkedList::from([term, operand(lhs), operand(rhs)]), span }
+    }
+}
+
+/// Punctuation that can't be declared as an operator, because the rest of the syntax
+/// already uses it, like `?` for holes, `:` for annotations and `->` for arms and types.
+pub const RESERVED: [&str; 10] = ["\\", ".", "#", "=", "?", ":", ",", ";", "|", "->"];
+/// Operators can't start like a comment.
+pub const COMMENTS: [&str; 3] = ["--", "//", "/*"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Operators {
+    pub operators: Vec<Operator>,
+}
+
+impl Operators {
+    /// Declares an operator, replacing the one with the same symbol.
+    pub fn insert(&mut self, operator: Operator) {
+        self.operators.retain(|declared| declared.symbol != operator.symbol);
+        self.operators.push(operator);
+    }
+    pub fn symbols(&self) -> Vec<String> {
+        self.operators.iter().map(|operator| operator.symbol.clone()).collect()
+    }
+    /// The operator that the next token is, if it is one.
+    pub fn peek(&self, tokens: &Tokens) -> Option<&Operator> {
+        let punct = tokens.tokens.get(tokens.next)?.get_punct()?;
+        self.operators.iter().find(|operator| operator.symbol == punct.punct)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{App, calculator::Calculator};
+
+    use super::*;
+
+    /// A calculator where `symbol` is declared as the term `f`.
+    fn with_operator(symbol: &str) -> Calculator {
+        let mut calculator = Calculator::empty();
+        let term = calculator.parse("f").unwrap();
+        calculator.operators.insert(Operator { symbol: symbol.to_string(), associativity: Associativity::None, precedence: 4, term });
+        calculator
+    }
+
+    #[test]
+    fn operator_starting_with_angle_bracket() {
+        for symbol in ["<=", "<", "<>"] {
+            let calculator = with_operator(symbol);
+            let statements = calculator.parse(&format!("a {symbol} b")).unwrap();
+            assert!(statements.alpha_equivalent(&calculator.parse("f a b").unwrap()), "{symbol}");
+        }
+    }
+
+    #[test]
+    fn reserved_punctuation_is_not_an_operator() {
+        let app = App::new();
+        for symbol in RESERVED {
+            let declaration = format!("infixl 6 {symbol} = f");
+            assert_eq!(app.parse_operator(&declaration), Some(Err(format!("'{symbol}' can't be used as an operator"))), "{symbol}");
+        }
+        assert!(matches!(app.parse_operator("infixl 6 ++ = f"), Some(Ok(_))));
+    }
+}
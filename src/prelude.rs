@@ -0,0 +1,351 @@
+use crate::app::recursion::{Y_COMBINATOR, Z_COMBINATOR};
+
+/// A macro of the prelude.
+pub struct Entry {
+    /// The name with its '#'.
+    pub name: &'static str,
+    pub body: &'static str,
+    pub doc: &'static str,
+    /// Terms that use the macro and the terms they have to normalize to,
+    /// which `prelude(check)` runs through the evaluator with every module loaded.
+    pub examples: &'static [(&'static str, &'static str)],
+}
+
+/// Macros that get loaded together, after the modules they use.
+pub struct Module {
+    pub name: &'static str,
+    pub doc: &'static str,
+    pub requires: &'static [&'static str],
+    pub entries: &'static [Entry],
+}
+
+pub const PRELUDE: &[Module] = &[
+    Module {
+        name: "bool",
+        doc: "Church booleans, which pick one of two terms",
+        requires: &[],
+        entries: &[
+            Entry {
+                name: "#true",
+                body: "\\t f. t",
+                doc: "Picks the first of two terms.",
+                examples: &[("#true a b", "a")],
+            },
+            Entry {
+                name: "#false",
+                body: "\\t f. f",
+                doc: "Picks the second of two terms.",
+                examples: &[("#false a b", "b")],
+            },
+            Entry {
+                name: "#if",
+                body: "\\c t e. c t e",
+                doc: "`#if c t e` is `t` when `c` is true and `e` when it's false.",
+                examples: &[("#if #true a b", "a"), ("#if #false a b", "b")],
+            },
+            Entry {
+                name: "#not",
+                body: "\\b. b #false #true",
+                doc: "Negation.",
+                examples: &[("#not #true", "#false"), ("#not #false", "#true")],
+            },
+            Entry {
+                name: "#and",
+                body: "\\a b. a b #false",
+                doc: "Conjunction, true when both are.",
+                examples: &[("#and #true #true", "#true"), ("#and #true #false", "#false"), ("#and #false #true", "#false")],
+            },
+            Entry {
+                name: "#or",
+                body: "\\a b. a #true b",
+                doc: "Disjunction, true when either is.",
+                examples: &[("#or #false #false", "#false"), ("#or #false #true", "#true"), ("#or #true #false", "#true")],
+            },
+            Entry {
+                name: "#xor",
+                body: "\\a b. a (#not b) b",
+                doc: "Exclusive or, true when exactly one is.",
+                examples: &[("#xor #true #true", "#false"), ("#xor #true #false", "#true"), ("#xor #false #false", "#false")],
+            },
+        ],
+    },
+    Module {
+        name: "nat",
+        doc: "Church numerals, `n` applies a function `n` times, and numbers like `3` are written as they are",
+        requires: &["bool"],
+        entries: &[
+            Entry {
+                name: "#succ",
+                body: "\\n f x. f (n f x)",
+                doc: "The number after `n`.",
+                examples: &[("#succ 0", "1"), ("#succ 2", "3")],
+            },
+            Entry {
+                name: "#pred",
+                body: "\\n f x. n (\\g h. h (g f)) (\\u. x) (\\u. u)",
+                doc: "The number before `n`, and 0 for 0.",
+                examples: &[("#pred 3", "2"), ("#pred 0", "0")],
+            },
+            Entry {
+                name: "#add",
+                body: "\\m n f x. m f (n f x)",
+                doc: "Addition.",
+                examples: &[("#add 2 3", "5"), ("#add 0 2", "2")],
+            },
+            Entry {
+                name: "#sub",
+                body: "\\m n. n #pred m",
+                doc: "Subtraction, which stops at 0.",
+                examples: &[("#sub 5 2", "3"), ("#sub 2 5", "0")],
+            },
+            Entry {
+                name: "#mul",
+                body: "\\m n f. m (n f)",
+                doc: "Multiplication.",
+                examples: &[("#mul 2 3", "6"), ("#mul 0 3", "0")],
+            },
+            Entry {
+                name: "#pow",
+                body: "\\b e. e (#mul b) 1",
+                doc: "`#pow b e` is `b` to the power of `e`.",
+                examples: &[("#pow 2 3", "8"), ("#pow 3 0", "1")],
+            },
+            Entry {
+                name: "#iszero",
+                body: "\\n. n (\\x. #false) #true",
+                doc: "Whether `n` is 0.",
+                examples: &[("#iszero 0", "#true"), ("#iszero 2", "#false")],
+            },
+            Entry {
+                name: "#leq",
+                body: "\\m n. #iszero (#sub m n)",
+                doc: "Whether `m` is at most `n`.",
+                examples: &[("#leq 2 3", "#true"), ("#leq 3 3", "#true"), ("#leq 3 2", "#false")],
+            },
+            Entry {
+                name: "#lt",
+                body: "\\m n. #not (#leq n m)",
+                doc: "Whether `m` is less than `n`.",
+                examples: &[("#lt 2 3", "#true"), ("#lt 3 3", "#false")],
+            },
+            Entry {
+                name: "#eq",
+                body: "\\m n. #and (#leq m n) (#leq n m)",
+                doc: "Whether `m` and `n` are the same number.",
+                examples: &[("#eq 3 3", "#true"), ("#eq 2 3", "#false"), ("#eq 3 2", "#false")],
+            },
+        ],
+    },
+    Module {
+        name: "pair",
+        doc: "Church pairs, which give both of their values to a function",
+        requires: &[],
+        entries: &[
+            Entry {
+                name: "#pair",
+                body: "\\a b k. k a b",
+                doc: "The pair of `a` and `b`.",
+                examples: &[("#pair a b (\\x y. y x)", "b a")],
+            },
+            Entry {
+                name: "#fst",
+                body: "\\p. p (\\a b. a)",
+                doc: "The first value of a pair.",
+                examples: &[("#fst (#pair a b)", "a")],
+            },
+            Entry {
+                name: "#snd",
+                body: "\\p. p (\\a b. b)",
+                doc: "The second value of a pair.",
+                examples: &[("#snd (#pair a b)", "b")],
+            },
+        ],
+    },
+    Module {
+        name: "list",
+        doc: "Church lists, which are their own right fold",
+        requires: &["bool", "nat"],
+        entries: &[
+            Entry {
+                name: "#nil",
+                body: "\\c n. n",
+                doc: "The empty list.",
+                examples: &[("#nil c n", "n")],
+            },
+            Entry {
+                name: "#cons",
+                body: "\\h t c n. c h (t c n)",
+                doc: "The list with the head `h` and the tail `t`.",
+                examples: &[("#cons a (#cons b #nil) c n", "c a (c b n)")],
+            },
+            Entry {
+                name: "#isnil",
+                body: "\\l. l (\\h t. #false) #true",
+                doc: "Whether the list is empty.",
+                examples: &[("#isnil #nil", "#true"), ("#isnil (#cons a #nil)", "#false")],
+            },
+            Entry {
+                name: "#head",
+                body: "\\l. l (\\h t. h) #false",
+                doc: "The first value of the list, and `#false` for the empty list.",
+                examples: &[("#head (#cons a (#cons b #nil))", "a"), ("#head #nil", "#false")],
+            },
+            Entry {
+                name: "#fold",
+                body: "\\f z l. l f z",
+                doc: "`#fold f z l` replaces the conses of `l` with `f` and the end with `z`.",
+                examples: &[("#fold f z (#cons a (#cons b #nil))", "f a (f b z)")],
+            },
+            Entry {
+                name: "#map",
+                body: "\\f l c n. l (\\h t. c (f h) t) n",
+                doc: "Applies `f` to every value of the list.",
+                examples: &[("#map #succ (#cons 1 (#cons 2 #nil))", "#cons 2 (#cons 3 #nil)"), ("#map f #nil", "#nil")],
+            },
+            Entry {
+                name: "#filter",
+                body: "\\p l c n. l (\\h t. p h (c h t) t) n",
+                doc: "The values of the list that `p` is true for.",
+                examples: &[("#filter #iszero (#cons 0 (#cons 1 (#cons 0 #nil)))", "#cons 0 (#cons 0 #nil)")],
+            },
+            Entry {
+                name: "#length",
+                body: "\\l. l (\\h. #succ) 0",
+                doc: "How many values the list has.",
+                examples: &[("#length (#cons a (#cons b #nil))", "2"), ("#length #nil", "0")],
+            },
+            Entry {
+                name: "#sum",
+                body: "\\l. l #add 0",
+                doc: "The sum of a list of numbers.",
+                examples: &[("#sum (#cons 1 (#cons 2 (#cons 3 #nil)))", "6")],
+            },
+            Entry {
+                name: "#append",
+                body: "\\a b c n. a c (b c n)",
+                doc: "The values of `a` followed by the values of `b`.",
+                examples: &[("#append (#cons a #nil) (#cons b #nil)", "#cons a (#cons b #nil)")],
+            },
+        ],
+    },
+    Module {
+        name: "fix",
+        doc: "Fixed point combinators, for recursion without recursive macros",
+        requires: &[],
+        entries: &[
+            Entry {
+                name: "#Y",
+                body: Y_COMBINATOR,
+                doc: "`#Y f` is `f (#Y f)`, it only terminates in normal order.",
+                examples: &[("#Y (\\r n. #iszero n 0 (#add n (r (#pred n)))) 3", "6")],
+            },
+            Entry {
+                name: "#Z",
+                body: Z_COMBINATOR,
+                doc: "The fixed point combinator that also terminates with call by value.",
+                examples: &[("#Z (\\r n. #iszero n 0 (#add n (r (#pred n)))) 3", "6")],
+            },
+        ],
+    },
+    Module {
+        name: "scott",
+        doc: "Scott encodings, where a value is its own case analysis",
+        requires: &["bool"],
+        entries: &[
+            Entry {
+                name: "#scott_zero",
+                body: "\\z s. z",
+                doc: "The Scott numeral 0.",
+                examples: &[("#scott_zero z s", "z")],
+            },
+            Entry {
+                name: "#scott_succ",
+                body: "\\n z s. s n",
+                doc: "The Scott numeral after `n`, which gives `n` to its second case.",
+                examples: &[("#scott_succ n z s", "s n")],
+            },
+            Entry {
+                name: "#scott_pred",
+                body: "\\n. n #scott_zero (\\p. p)",
+                doc: "The Scott numeral before `n`, in one step.",
+                examples: &[("#scott_pred (#scott_succ (#scott_succ #scott_zero))", "#scott_succ #scott_zero"), ("#scott_pred #scott_zero", "#scott_zero")],
+            },
+            Entry {
+                name: "#scott_iszero",
+                body: "\\n. n #true (\\p. #false)",
+                doc: "Whether the Scott numeral is 0.",
+                examples: &[("#scott_iszero #scott_zero", "#true"), ("#scott_iszero (#scott_succ #scott_zero)", "#false")],
+            },
+            Entry {
+                name: "#scott_nil",
+                body: "\\n c. n",
+                doc: "The empty Scott list.",
+                examples: &[("#scott_nil n c", "n")],
+            },
+            Entry {
+                name: "#scott_cons",
+                body: "\\h t n c. c h t",
+                doc: "The Scott list with the head `h` and the tail `t`.",
+                examples: &[("#scott_cons h t n c", "c h t")],
+            },
+            Entry {
+                name: "#scott_isnil",
+                body: "\\l. l #true (\\h t. #false)",
+                doc: "Whether the Scott list is empty.",
+                examples: &[("#scott_isnil #scott_nil", "#true"), ("#scott_isnil (#scott_cons a #scott_nil)", "#false")],
+            },
+            Entry {
+                name: "#scott_head",
+                body: "\\l. l #false (\\h t. h)",
+                doc: "The first value of the Scott list, and `#false` for the empty list.",
+                examples: &[("#scott_head (#scott_cons a #scott_nil)", "a"), ("#scott_head #scott_nil", "#false")],
+            },
+            Entry {
+                name: "#scott_tail",
+                body: "\\l. l #scott_nil (\\h t. t)",
+                doc: "The Scott list without its first value.",
+                examples: &[("#scott_head (#scott_tail (#scott_cons a (#scott_cons b #scott_nil)))", "b")],
+            },
+        ],
+    },
+];
+
+/// The module of the prelude with the name.
+pub fn module(name: &str) -> Option<&'static Module> {
+    PRELUDE.iter().find(|module| module.name == name)
+}
+/// The macro of the prelude with the name.
+pub fn entry(name: &str) -> Option<&'static Entry> {
+    PRELUDE.iter().flat_map(|module| module.entries).find(|entry| entry.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+
+    use super::*;
+
+    #[test]
+    fn examples_have_the_normal_forms_they_should() {
+        let (failures, total) = App::check_examples();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+        assert_eq!(total, PRELUDE.iter().flat_map(|module| module.entries).map(|entry| entry.examples.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn every_entry_has_examples() {
+        for entry in PRELUDE.iter().flat_map(|module| module.entries) {
+            assert!(!entry.examples.is_empty(), "{} has no examples", entry.name);
+        }
+    }
+
+    #[test]
+    fn modules_come_after_the_modules_they_require() {
+        for (i, module) in PRELUDE.iter().enumerate() {
+            for required in module.requires {
+                let position = PRELUDE.iter().position(|other| other.name == *required);
+                assert!(position.is_some_and(|position| position < i), "{} requires {required}", module.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::ast::{Statement, Statements};
+
+/// How terms get printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrintOptions {
+    /// Print abstractions with 'λ' instead of '\'.
+    pub unicode: bool,
+    /// Break terms that don't fit in this many characters over several lines.
+    pub width: Option<usize>,
+    /// Replace everything nested deeper than this with '…'.
+    pub depth: Option<usize>,
+    /// Print the parts of the terms that evaluate that are the normal form of a macro as its name.
+    pub fold: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self { unicode: true, width: None, depth: None, fold: false }
+    }
+}
+
+/// The shape of a term once the parenthesis of the source are forgotten.
+enum Layout {
+    Variable(String),
+    /// λvariables.body, with curried abstractions collapsed into one.
+    /// The variables include their annotations.
+    Abstraction(Vec<String>, Box<Layout>),
+    /// Λvariables.body
+    TypeAbstraction(Vec<String>, Box<Layout>),
+    /// [type], only ever an input.
+    Type(String),
+    /// case scrutinee of { patterns -> bodies }
+    Case(Box<Layout>, Vec<(String, Layout)>),
+    /// head arguments...
+    Application(Box<Layout>, Vec<Layout>),
+    /// Something that was deeper than the depth limit.
+    Elided,
+}
+
+/// Where a term is printed, which decides if it needs parenthesis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// At the top, inside of parenthesis, or the body of an abstraction.
+    Free,
+    /// The function of an application.
+    Head,
+    /// An input of an application followed by more inputs.
+    Input,
+    /// The last input of an application, which an abstraction can extend to the end of.
+    LastInput,
+}
+
+impl Layout {
+    fn from_statement(statement: &Statement, depth: usize, options: &PrintOptions) -> Self {
+        if options.depth.is_some_and(|max| depth > max) {
+            return Layout::Elided;
+        }
+        match statement {
+            Statement::Term(term) => Layout::Variable(term.name.ident.clone()),
+            Statement::Group(group) => Self::from_statements(&group.statements, depth, options),
+            Statement::Abstraction(abstraction) => {
+                let body = Self::from_statements(&abstraction.next, depth + 1, options);
+                let variable = match &abstraction.annotation {
+                    Some(annotation) if annotation.is_atom() => format!("{}:{annotation}", abstraction.variable),
+                    Some(annotation) => format!("{}:({annotation})", abstraction.variable),
+                    None => abstraction.variable.name.ident.clone(),
+                };
+                match body {
+                    Layout::Abstraction(mut variables, body) => {
+                        variables.insert(0, variable);
+                        Layout::Abstraction(variables, body)
+                    }
+                    body => Layout::Abstraction(vec![variable], Box::new(body)),
+                }
+            }
+            Statement::TypeAbstraction(abstraction) => {
+                let variable = abstraction.variable.ident.clone();
+                match Self::from_statements(&abstraction.next, depth + 1, options) {
+                    Layout::TypeAbstraction(mut variables, body) => {
+                        variables.insert(0, variable);
+                        Layout::TypeAbstraction(variables, body)
+                    }
+                    body => Layout::TypeAbstraction(vec![variable], Box::new(body)),
+                }
+            }
+            Statement::TypeApplication(application) => Layout::Type(format!("[{}]", application.ty)),
+            Statement::Hole(hole) => Layout::Variable(format!("?{}", hole.name)),
+            Statement::Macro(reference) if reference.arguments.is_empty() => Layout::Variable(reference.name.clone()),
+            Statement::Macro(reference) => {
+                let arguments: Vec<String> = reference.arguments.iter().map(|argument| options.statements(argument)).collect();
+                Layout::Variable(format!("{}({})", reference.name, arguments.join(", ")))
+            }
+            Statement::Case(case) => {
+                let scrutinee = Self::from_statements(&case.scrutinee, depth + 1, options);
+                let arms = case.arms.iter().map(|arm| {
+                    let mut pattern = arm.constructor.ident.clone();
+                    for binding in arm.bindings.iter() {
+                        pattern.push(' ');
+                        pattern.push_str(&binding.name.ident);
+                    }
+                    (pattern, Self::from_statements(&arm.body, depth + 1, options))
+                }).collect();
+                Layout::Case(Box::new(scrutinee), arms)
+            }
+        }
+    }
+    fn from_statements(statements: &Statements, depth: usize, options: &PrintOptions) -> Self {
+        let mut iter = statements.statements.iter();
+        let Some(head) = iter.next() else {
+            return Layout::Variable(String::new());
+        };
+        let head = Self::from_statement(head, depth, options);
+        let inputs: Vec<Layout> = iter.map(|input| Self::from_statement(input, depth + 1, options)).collect();
+        if inputs.is_empty() {
+            return head;
+        }
+        // Applications are left associative, so (f a) b is f a b
+        match head {
+            Layout::Application(head, mut first) => {
+                first.extend(inputs);
+                Layout::Application(head, first)
+            }
+            head => Layout::Application(Box::new(head), inputs),
+        }
+    }
+    fn needs_parenthesis(&self, position: Position) -> bool {
+        match self {
+            Layout::Variable(_) | Layout::Type(_) | Layout::Case(..) | Layout::Elided => false,
+            Layout::Abstraction(..) | Layout::TypeAbstraction(..) => matches!(position, Position::Head | Position::Input),
+            Layout::Application(..) => matches!(position, Position::Input | Position::LastInput),
+        }
+    }
+    fn flat(&self, position: Position, options: &PrintOptions) -> String {
+        let text = match self {
+            Layout::Variable(name) | Layout::Type(name) => name.clone(),
+            Layout::Elided => elision(options).to_string(),
+            Layout::Abstraction(variables, body) => {
+                format!("{}{}.{}", lambda(options), variables.join(" "), body.flat(Position::Free, options))
+            }
+            Layout::TypeAbstraction(variables, body) => {
+                format!("{}{}.{}", type_lambda(options), variables.join(" "), body.flat(Position::Free, options))
+            }
+            Layout::Case(scrutinee, arms) => {
+                let arms: Vec<String> = arms.iter()
+                    .map(|(pattern, body)| format!("{pattern} -> {}", body.flat(Position::Free, options)))
+                    .collect();
+                format!("case {} of {{ {} }}", scrutinee.flat(Position::Free, options), arms.join("; "))
+            }
+            Layout::Application(head, inputs) => {
+                let mut text = head.flat(Position::Head, options);
+                for (i, input) in inputs.iter().enumerate() {
+                    text.push(' ');
+                    text.push_str(&input.flat(input_position(i, inputs.len()), options));
+                }
+                text
+            }
+        };
+        if self.needs_parenthesis(position) {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+    /// Prints the term, breaking it over several lines if it doesn't fit.
+    /// `indent` is the column the term starts at.
+    fn wrapped(&self, position: Position, indent: usize, options: &PrintOptions) -> String {
+        let flat = self.flat(position, options);
+        let Some(width) = options.width else {
+            return flat;
+        };
+        if indent + flat.chars().count() <= width {
+            return flat;
+        }
+        let parenthesis = self.needs_parenthesis(position);
+        let inner = indent + parenthesis as usize;
+        let newline = format!("\n{}", " ".repeat(inner + 2));
+        let text = match self {
+            Layout::Variable(_) | Layout::Type(_) | Layout::Case(..) | Layout::Elided => return flat,
+            Layout::Abstraction(variables, body) => {
+                format!("{}{}.{newline}{}", lambda(options), variables.join(" "), body.wrapped(Position::Free, inner + 2, options))
+            }
+            Layout::TypeAbstraction(variables, body) => {
+                format!("{}{}.{newline}{}", type_lambda(options), variables.join(" "), body.wrapped(Position::Free, inner + 2, options))
+            }
+            Layout::Application(head, inputs) => {
+                let mut text = head.wrapped(Position::Head, inner, options);
+                for (i, input) in inputs.iter().enumerate() {
+                    text.push_str(&newline);
+                    text.push_str(&input.wrapped(input_position(i, inputs.len()), inner + 2, options));
+                }
+                text
+            }
+        };
+        if parenthesis {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+}
+
+fn input_position(i: usize, len: usize) -> Position {
+    if i + 1 == len {
+        Position::LastInput
+    } else {
+        Position::Input
+    }
+}
+fn lambda(options: &PrintOptions) -> &'static str {
+    if options.unicode { "λ" } else { "\\" }
+}
+fn type_lambda(options: &PrintOptions) -> &'static str {
+    if options.unicode { "Λ" } else { "/\\" }
+}
+fn elision(options: &PrintOptions) -> &'static str {
+    if options.unicode { "…" } else { "..." }
+}
+
+impl PrintOptions {
+    pub fn statements(&self, statements: &Statements) -> String {
+        Layout::from_statements(statements, 0, self).wrapped(Position::Free, 0, self)
+    }
+    pub fn statement(&self, statement: &Statement) -> String {
+        Layout::from_statement(statement, 0, self).wrapped(Position::Free, 0, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    /// Prints the text with the options.
+    fn print(text: &str, options: PrintOptions) -> String {
+        options.statements(&Calculator::empty().parse(text).unwrap())
+    }
+
+    #[test]
+    fn only_needed_parenthesis_are_printed() {
+        let options = PrintOptions::default();
+        assert_eq!(print("((f a) b)", options), "f a b");
+        assert_eq!(print("f (a b)", options), "f (a b)");
+        assert_eq!(print("(\\x. x) y", options), "(λx.x) y");
+        assert_eq!(print("f (\\x. x)", options), "f λx.x");
+        assert_eq!(print("f (\\x. x) y", options), "f (λx.x) y");
+        assert_eq!(print("\\x. (x y)", options), "λx.x y");
+    }
+
+    #[test]
+    fn curried_abstractions_are_collapsed() {
+        assert_eq!(print("\\x. \\y. (\\z. x)", PrintOptions::default()), "λx y z.x");
+        assert_eq!(print("\\x. \\y. x", PrintOptions { unicode: false, ..PrintOptions::default() }), "\\x y.x");
+        assert_eq!(print("\\x. (\\y. y) x", PrintOptions::default()), "λx.(λy.y) x");
+    }
+
+    #[test]
+    fn long_terms_are_wrapped_at_the_width() {
+        let options = PrintOptions { width: Some(12), ..PrintOptions::default() };
+        assert_eq!(print("f a b", options), "f a b");
+        let wrapped = print("\\x. fun alpha beta gamma", options);
+        assert_eq!(wrapped, "λx.\n  fun\n    alpha\n    beta\n    gamma");
+        assert!(wrapped.lines().all(|line| line.chars().count() <= 12));
+    }
+
+    #[test]
+    fn terms_beyond_the_depth_are_elided() {
+        let options = PrintOptions { depth: Some(1), ..PrintOptions::default() };
+        assert_eq!(print("f (g (h x))", options), "f (g …)");
+        let options = PrintOptions { depth: Some(0), unicode: false, ..PrintOptions::default() };
+        assert_eq!(print("f (g x)", options), "f ...");
+    }
+}
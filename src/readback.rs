@@ -0,0 +1,296 @@
+use std::collections::{HashMap, LinkedList};
+
+use crate::{ast::{Statement, Statements}, printer::PrintOptions, types::{Type, inference::Inference}};
+
+/// One field of a case of a Church encoded type.
+enum Field {
+    /// The field is the fold of a value of the type itself.
+    Recursive,
+    Value(Type),
+}
+
+/// The Church encoded types that have a readable form.
+enum Kind {
+    /// `(a -> a) -> a -> a` prints as a number.
+    Nat,
+    /// `a -> a -> a` prints as true or false.
+    Bool,
+    /// `(T -> b -> b) -> b -> b` prints as [x, y, z].
+    List,
+    /// `(A -> B -> c) -> c` prints as (x, y).
+    Tuple,
+}
+
+/// A Church encoded type, a value takes one function for every case and
+/// gives back whatever they give back, like `forall r. (a -> r -> r) -> r -> r`.
+struct Shape {
+    cases: Vec<Vec<Field>>,
+    /// How many inputs that are types come before the cases, like the `A` of `Pi A:Type.`.
+    types: usize,
+}
+
+/// A value that was decoded, which case made it and its fields.
+struct Node {
+    case: usize,
+    fields: Vec<Decoded>,
+}
+
+enum Decoded {
+    Node(Node),
+    Value(String),
+}
+
+impl Shape {
+    fn of(ty: &Type) -> Option<Self> {
+        let mut ty = ty;
+        let mut types = 0;
+        loop {
+            match ty {
+                Type::Forall(_, body) => ty = body,
+                // The type variables of the Calculus of Constructions are inputs of the value
+                Type::Pi(_, from, body) if matches!(**from, Type::Universe(_)) => {
+                    ty = body;
+                    types += 1;
+                }
+                _ => break,
+            }
+        }
+        let (cases, result) = arrows(ty);
+        if !matches!(result, Type::Base(_) | Type::Variable(_)) {
+            return None;
+        }
+        let cases = cases.into_iter().map(|case| {
+            let (fields, output) = arrows(case);
+            if output != result {
+                return None;
+            }
+            fields.into_iter().map(|field| match field {
+                field if field == result => Some(Field::Recursive),
+                field if mentions(field, result) => None,
+                field => Some(Field::Value(field.clone())),
+            }).collect()
+        }).collect::<Option<Vec<Vec<Field>>>>()?;
+        Some(Self { cases, types })
+    }
+    fn kind(&self) -> Option<Kind> {
+        let fields: Vec<Vec<bool>> = self.cases.iter()
+            .map(|fields| fields.iter().map(|field| matches!(field, Field::Recursive)).collect())
+            .collect();
+        let fields: Vec<&[bool]> = fields.iter().map(Vec::as_slice).collect();
+        match fields.as_slice() {
+            [[], []] => Some(Kind::Bool),
+            [[], [true]] | [[true], []] => Some(Kind::Nat),
+            [[], [false, true]] | [[false, true], []] => Some(Kind::List),
+            [fields] if fields.len() >= 2 && fields.iter().all(|recursive| !recursive) => Some(Kind::Tuple),
+            _ => None,
+        }
+    }
+    /// Decodes the body of a value, where the functions of the cases are `cases`.
+    fn node(&self, cases: &[String], statements: &Statements, options: &PrintOptions) -> Option<Node> {
+        let (head, inputs) = spine(statements)?;
+        let Statement::Term(term) = head else {
+            return None;
+        };
+        let case = cases.iter().position(|case| *case == term.name.ident)?;
+        if inputs.len() != self.cases[case].len() {
+            return None;
+        }
+        let fields = self.cases[case].iter().zip(inputs).map(|(field, input)| {
+            let input = as_statements(input);
+            match field {
+                Field::Recursive => self.node(cases, &input, options).map(Decoded::Node),
+                Field::Value(ty) => Some(Decoded::Value(read_back(ty, &input, options).unwrap_or(options.statements(&input)))),
+            }
+        }).collect::<Option<Vec<Decoded>>>()?;
+        Some(Node { case, fields })
+    }
+}
+
+/// Prints a normal form the way its type says it should be read, like `3` for a
+/// Church numeral that has type `(a -> a) -> a -> a`. Terms are only read back when
+/// their type is certain, `\f x. x` has type `a -> b -> b` and could be 0 or false.
+/// A type that is more general than a number or a list, like the `(a -> b) -> a -> b`
+/// of `\f x. f x`, is read as the one of them that fits the term, if only one does.
+pub fn read_back(ty: &Type, statements: &Statements, options: &PrintOptions) -> Option<String> {
+    if let Some(shape) = Shape::of(ty) && let Some(kind) = shape.kind() {
+        return decode(&shape, kind, statements, options);
+    }
+    let mut readings = specializations(ty).into_iter().filter_map(|ty| {
+        let shape = Shape::of(&ty)?;
+        let kind = shape.kind()?;
+        decode(&shape, kind, statements, options)
+    });
+    let reading = readings.next()?;
+    readings.next().is_none().then_some(reading)
+}
+/// The types of numbers and lists that the type can be made into by picking its variables.
+fn specializations(ty: &Type) -> Vec<Type> {
+    let mut variables = vec![];
+    ty.collect_variables(&mut variables);
+    let macros = HashMap::new();
+    let mut specializations = vec![];
+    for list in [false, true] {
+        let mut inference = Inference::new(&macros);
+        // The variables of the template come after the ones of the type
+        for _ in 0..variables.iter().max().map_or(0, |max| max + 1) {
+            inference.fresh();
+        }
+        let output = inference.fresh();
+        let step = match list {
+            false => Type::arrow(output.clone(), output.clone()),
+            true => Type::arrow(inference.fresh(), Type::arrow(output.clone(), output.clone())),
+        };
+        let template = Type::arrow(step, Type::arrow(output.clone(), output));
+        if inference.unify(ty, &template).is_ok() {
+            specializations.push(inference.resolve(&template));
+        }
+    }
+    specializations
+}
+/// Reads the value back as the kind of Church encoded type its shape is.
+fn decode(shape: &Shape, kind: Kind, statements: &Statements, options: &PrintOptions) -> Option<String> {
+    let mut statements = statements.clone();
+    let mut cases = vec![];
+    for i in 0..shape.types + shape.cases.len() {
+        let statement = single(&statements)?;
+        let Statement::Abstraction(abstraction) = statement else {
+            return None;
+        };
+        if i >= shape.types {
+            cases.push(abstraction.variable.name.ident.clone());
+        }
+        statements = abstraction.next.clone();
+    }
+    let node = shape.node(&cases, &statements, options)?;
+    Some(match kind {
+        Kind::Bool => (if node.case == 0 { "true" } else { "false" }).to_string(),
+        Kind::Nat => {
+            let mut count = 0;
+            let mut node = &node;
+            while let [Decoded::Node(next)] = node.fields.as_slice() {
+                count += 1;
+                node = next;
+            }
+            count.to_string()
+        }
+        Kind::List => {
+            let mut elements = vec![];
+            let mut node = &node;
+            while let [Decoded::Value(element), Decoded::Node(next)] = node.fields.as_slice() {
+                elements.push(element.clone());
+                node = next;
+            }
+            format!("[{}]", elements.join(", "))
+        }
+        Kind::Tuple => {
+            let fields: Vec<String> = node.fields.iter().filter_map(|field| match field {
+                Decoded::Value(value) => Some(value.clone()),
+                Decoded::Node(_) => None,
+            }).collect();
+            format!("({})", fields.join(", "))
+        }
+    })
+}
+
+/// Splits `A -> B -> C` into its inputs `[A, B]` and output `C`.
+fn arrows(ty: &Type) -> (Vec<&Type>, &Type) {
+    let mut inputs = vec![];
+    let mut ty = ty;
+    while let Type::Arrow(from, to) = ty {
+        inputs.push(&**from);
+        ty = to;
+    }
+    (inputs, ty)
+}
+/// The output of a Church encoded type can only be the output of its cases.
+fn mentions(ty: &Type, output: &Type) -> bool {
+    match output {
+        Type::Base(name) => ty.free_names().contains(name),
+        Type::Variable(variable) => {
+            let mut variables = vec![];
+            ty.collect_variables(&mut variables);
+            variables.contains(variable)
+        }
+        _ => false,
+    }
+}
+/// The statement that the statements are, without the parenthesis around it.
+fn single(statements: &Statements) -> Option<&Statement> {
+    match statements.statements.iter().collect::<Vec<_>>().as_slice() {
+        [Statement::Group(group)] => single(&group.statements),
+        [statement] => Some(statement),
+        _ => None,
+    }
+}
+/// The function and inputs of an application, `(f a) b` is `f` with `a` and `b`.
+fn spine(statements: &Statements) -> Option<(&Statement, Vec<&Statement>)> {
+    let mut iter = statements.statements.iter();
+    let head = iter.next()?;
+    let (head, mut inputs) = match head {
+        Statement::Group(group) => spine(&group.statements)?,
+        head => (head, vec![]),
+    };
+    inputs.extend(iter);
+    Some((head, inputs))
+}
+fn as_statements(statement: &Statement) -> Statements {
+    match statement {
+        Statement::Group(group) => group.statements.clone(),
+        statement => Statements { statements: LinkedList::from([statement.clone()]), span: statement.span().clone() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::{calculator::Calculator, state::{CalculatorState, Strategy}}, expansion::SourceMap};
+
+    use super::*;
+
+    /// Reads the normal form of the text back with the type, or with its inferred type.
+    fn read(text: &str, ty: Option<&str>) -> Option<String> {
+        let calculator = Calculator::empty();
+        let statements = calculator.parse(text).unwrap();
+        let ty = match ty {
+            Some(ty) => calculator.parse_type(ty).unwrap(),
+            None => Inference::new(&HashMap::new()).infer(&statements, &SourceMap::default()).unwrap(),
+        };
+        let normal = CalculatorState::normalize(statements, Strategy::NormalOrder, 1000).unwrap();
+        read_back(&ty, &normal, &PrintOptions::default())
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(read("\\f x. f (f x)", None).as_deref(), Some("2"));
+        assert_eq!(read("(\\n f x. f (n f x)) (\\f x. f (f x))", None).as_deref(), Some("3"));
+        assert_eq!(read("\\f x. f x", None).as_deref(), Some("1"));
+        assert_eq!(read("\\f x. x", Some("(a -> a) -> a -> a")).as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(read("\\t f. t", Some("a -> a -> a")).as_deref(), Some("true"));
+        assert_eq!(read("\\t f. f", Some("a -> a -> a")).as_deref(), Some("false"));
+    }
+
+    #[test]
+    fn lists() {
+        let two = "\\c n. c (\\f x. f x) (c (\\f x. f (f x)) n)";
+        assert_eq!(read(two, None).as_deref(), Some("[1, 2]"));
+        assert_eq!(read("\\c n. c (\\f x. f (f x)) n", None).as_deref(), Some("[2]"));
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(read("\\k. k (\\f x. f x) (\\f x. f (f x))", None).as_deref(), Some("(1, 2)"));
+        assert_eq!(read("\\k. k (\\f x. f (f x)) (\\t f. t)", Some("(((a -> a) -> a -> a) -> (b -> b -> b) -> c) -> c")).as_deref(), Some("(2, true)"));
+        assert_eq!(read("\\k. k (\\f x. f (f x)) (\\y. y)", None).as_deref(), Some("(2, λy.y)"));
+    }
+
+    #[test]
+    fn ambiguous_types_are_not_read_back() {
+        // 0, false and the empty list
+        assert_eq!(read("\\f x. x", None), None);
+        assert_eq!(read("\\x. x", None), None);
+        assert_eq!(read("\\a b. a", None), None);
+    }
+}
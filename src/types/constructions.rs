@@ -0,0 +1,380 @@
+use std::collections::LinkedList;
+
+use gelato_parser::lexer::{ident::Ident, span::Span};
+
+use crate::{app::state::{CalculatorState, Strategy}, ast::{Abstraction, Group, Statement, Statements, Term, fresh_name}, error::ASTError, types::Type};
+
+/// How many steps a type gets to reach its normal form.
+const NORMALIZATION_STEPS: usize = 10_000;
+/// The constant a pi type becomes while it's normalized, `Π A (λx.B)`.
+/// It can't be written, so it can't be mistaken for a variable.
+const PI: &str = "Π";
+/// Universes become constants like `Type 0` while they're normalized.
+const UNIVERSE: &str = "Type ";
+
+/// Checks terms of the Calculus of Constructions, with the universes
+/// Type 0 : Type 1 : Type 2... Type 0 is impredicative, a function type
+/// that gives a Type 0 is a Type 0 whatever it takes, so the Church encoded
+/// types are in it. Types are compared by normalizing them with the
+/// evaluator. A variable that would shadow one in scope gets renamed, so the
+/// types in the context keep their meaning.
+pub struct ConstructionsChecker {
+    /// The type of every variable in scope by the name it was given, the innermost last.
+    context: Vec<(String, Type)>,
+    /// The variables in scope, as they were written and the name they were given.
+    names: Vec<(String, String)>,
+}
+
+impl ConstructionsChecker {
+    pub fn new() -> Self {
+        Self { context: vec![], names: vec![] }
+    }
+    /// Finds the type of the statements, or the first place they go wrong.
+    pub fn check(&mut self, statements: &Statements) -> Result<Type, ASTError> {
+        let mut iter = statements.statements.iter();
+        let head = iter.next().ok_or(ASTError::Type("Expected a term".to_string(), statements.span.clone()))?;
+        let mut ty = self.check_statement(head)?;
+        let mut function = head.span().clone();
+        for input in iter {
+            // Types are terms too, [T] is the type T as an input
+            let (input_ty, value) = match input {
+                Statement::TypeApplication(application) => {
+                    (self.check_type(&application.ty, &application.span)?, self.type_value(&application.ty))
+                }
+                input => (self.check_statement(input)?, self.value(input)),
+            };
+            ty = Self::apply(ty, &input_ty, value, &function, input.span())?;
+            function = function.join(input.span());
+        }
+        Ok(ty)
+    }
+    /// The type of `function input`, where the function has type `ty`.
+    fn apply(ty: Type, input_ty: &Type, value: Type, function: &Span, span: &Span) -> Result<Type, ASTError> {
+        let (variable, from, to) = match ty {
+            Type::Pi(variable, from, to) => (Some(variable), *from, *to),
+            Type::Arrow(from, to) => (None, *from, *to),
+            ty => return Err(ASTError::Type(format!("This has type {ty}, it can't be applied to anything"), function.clone())),
+        };
+        if !convertible(input_ty, &from) {
+            return Err(ASTError::Type(format!("Expected an input of type {from}, found {input_ty}"), span.clone()));
+        }
+        match variable {
+            Some(variable) => normalize(&Type::apply(Type::lambda(&variable, None, to), value), span),
+            None => Ok(to),
+        }
+    }
+    fn check_statement(&mut self, statement: &Statement) -> Result<Type, ASTError> {
+        match statement {
+            Statement::Term(term) if term.name.ident == "Type" && self.given("Type").is_none() => Ok(Type::Universe(1)),
+            Statement::Term(term) => self.lookup(&term.name.ident, &term.name.span),
+            Statement::Group(group) => self.check(&group.statements),
+            Statement::Abstraction(abstraction) => {
+                let variable = &abstraction.variable.name;
+                let Some(annotation) = &abstraction.annotation else {
+                    return Err(ASTError::Type(format!("'{}' needs a type annotation", variable.ident), variable.span.clone()));
+                };
+                let domain = self.domain(annotation, &variable.span)?;
+                let given = self.bind(&variable.ident, domain.clone());
+                let body = self.check(&abstraction.next);
+                self.unbind();
+                Ok(pi(&given, domain, body?))
+            }
+            // /\a. e is \a:Type. e
+            Statement::TypeAbstraction(abstraction) => {
+                let given = self.bind(&abstraction.variable.ident, Type::Universe(0));
+                let body = self.check(&abstraction.next);
+                self.unbind();
+                Ok(pi(&given, Type::Universe(0), body?))
+            }
+            Statement::TypeApplication(application) => {
+                Err(ASTError::Type("A type application needs a term before it".to_string(), application.span.clone()))
+            }
+            Statement::Hole(hole) => {
+                Err(ASTError::Type("Holes are filled in with mode(infer)".to_string(), hole.span.clone()))
+            }
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+        }
+    }
+    /// The type of a term that was written with the syntax of types.
+    /// Types don't have spans, so errors point at `span`.
+    pub fn check_type(&mut self, ty: &Type, span: &Span) -> Result<Type, ASTError> {
+        match ty {
+            Type::Base(name) => self.lookup(name, span),
+            Type::Universe(level) => Ok(Type::Universe(level + 1)),
+            Type::Arrow(from, to) => {
+                let from = self.universe(from, span)?;
+                let to = self.universe(to, span)?;
+                Ok(Type::Universe(level(from, to)))
+            }
+            Type::Forall(variable, ty) => {
+                self.bind(variable, Type::Universe(0));
+                let to = self.universe(ty, span);
+                self.unbind();
+                Ok(Type::Universe(level(1, to?)))
+            }
+            Type::Pi(variable, from, to) => {
+                let from_level = self.universe(from, span)?;
+                let domain = normalize(&self.type_value(from), span)?;
+                self.bind(variable, domain);
+                let to = self.universe(to, span);
+                self.unbind();
+                Ok(Type::Universe(level(from_level, to?)))
+            }
+            Type::Apply(function, input) => {
+                let ty = self.check_type(function, span)?;
+                let input_ty = self.check_type(input, span)?;
+                let value = self.type_value(input);
+                Self::apply(ty, &input_ty, value, span, span)
+            }
+            Type::Lambda(variable, Some(annotation), body) => {
+                let domain = self.domain(annotation, span)?;
+                let given = self.bind(variable, domain.clone());
+                let body = self.check_type(body, span);
+                self.unbind();
+                Ok(pi(&given, domain, body?))
+            }
+            Type::Lambda(variable, None, _) => Err(ASTError::Type(format!("'{variable}' needs a type annotation"), span.clone())),
+            Type::Variable(_) => unreachable!("only inference makes type variables"),
+        }
+    }
+    /// The level of the universe a type is in.
+    fn universe(&mut self, ty: &Type, span: &Span) -> Result<usize, ASTError> {
+        match self.check_type(ty, span)? {
+            Type::Universe(level) => Ok(level),
+            sort => Err(ASTError::Type(format!("{ty} isn't a type, it has type {sort}"), span.clone())),
+        }
+    }
+    /// Checks that the annotation of a binder is a type, and normalizes it.
+    fn domain(&mut self, annotation: &Type, span: &Span) -> Result<Type, ASTError> {
+        self.universe(annotation, span)?;
+        normalize(&self.type_value(annotation), span)
+    }
+    fn lookup(&self, name: &str, span: &Span) -> Result<Type, ASTError> {
+        let given = self.given(name).ok_or(ASTError::Type(format!("'{name}' is not bound"), span.clone()))?;
+        Ok(self.context.iter().rev().find(|(variable, _)| *variable == given).map(|(_, ty)| ty.clone()).unwrap())
+    }
+    fn given(&self, name: &str) -> Option<String> {
+        self.names.iter().rev().find(|(written, _)| written == name).map(|(_, given)| given.clone())
+    }
+    /// Puts a variable in scope, and returns the name it was given.
+    fn bind(&mut self, variable: &str, ty: Type) -> String {
+        let given = self.bind_name(variable);
+        self.context.push((given.clone(), ty));
+        given
+    }
+    fn unbind(&mut self) {
+        self.names.pop();
+        self.context.pop();
+    }
+    /// Gives a variable a name that no variable in scope has.
+    fn bind_name(&mut self, variable: &str) -> String {
+        let taken = self.names.iter().map(|(_, given)| given.clone()).collect();
+        let given = fresh_name(variable, &taken);
+        self.names.push((variable.to_string(), given.clone()));
+        given
+    }
+    /// The term a statement is, with the names its variables were given.
+    fn value(&mut self, statement: &Statement) -> Type {
+        match statement {
+            Statement::Term(term) => match self.given(&term.name.ident) {
+                Some(given) => Type::Base(given),
+                None if term.name.ident == "Type" => Type::Universe(0),
+                None => Type::Base(term.name.ident.clone()),
+            },
+            Statement::Group(group) => self.values(&group.statements),
+            Statement::Abstraction(abstraction) => {
+                let given = self.bind_name(&abstraction.variable.name.ident);
+                let body = self.values(&abstraction.next);
+                self.names.pop();
+                Type::lambda(&given, None, body)
+            }
+            Statement::TypeAbstraction(abstraction) => {
+                let given = self.bind_name(&abstraction.variable.ident);
+                let body = self.values(&abstraction.next);
+                self.names.pop();
+                Type::lambda(&given, None, body)
+            }
+            Statement::TypeApplication(application) => self.type_value(&application.ty),
+            Statement::Hole(hole) => Type::Base(format!("?{}", hole.name)),
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+        }
+    }
+    fn values(&mut self, statements: &Statements) -> Type {
+        let mut iter = statements.statements.iter();
+        let head = iter.next().map_or(Type::Base(String::new()), |head| self.value(head));
+        iter.fold(head, |function, input| Type::apply(function, self.value(input)))
+    }
+    /// The term a written type is, with the names its variables were given.
+    fn type_value(&mut self, ty: &Type) -> Type {
+        match ty {
+            Type::Base(name) => Type::Base(self.given(name).unwrap_or(name.clone())),
+            Type::Forall(variable, ty) => {
+                let given = self.bind_name(variable);
+                let ty = self.type_value(ty);
+                self.names.pop();
+                pi(&given, Type::Universe(0), ty)
+            }
+            Type::Pi(variable, from, to) => {
+                let from = self.type_value(from);
+                let given = self.bind_name(variable);
+                let to = self.type_value(to);
+                self.names.pop();
+                pi(&given, from, to)
+            }
+            Type::Lambda(variable, _, body) => {
+                let given = self.bind_name(variable);
+                let body = self.type_value(body);
+                self.names.pop();
+                Type::lambda(&given, None, body)
+            }
+            Type::Arrow(from, to) => Type::arrow(self.type_value(from), self.type_value(to)),
+            Type::Apply(function, input) => Type::apply(self.type_value(function), self.type_value(input)),
+            Type::Universe(_) | Type::Variable(_) => ty.clone(),
+        }
+    }
+}
+
+/// Evaluates the term of a type to its normal form.
+fn normalize(ty: &Type, span: &Span) -> Result<Type, ASTError> {
+    let mut state = CalculatorState { statements: to_statements(ty), strategy: Strategy::NormalOrder };
+    for _ in 0..NORMALIZATION_STEPS {
+        if !state.step().is_running() {
+            return Ok(from_statements(&state.statements));
+        }
+    }
+    Err(ASTError::Type(format!("{ty} doesn't reach a normal form within {NORMALIZATION_STEPS} steps"), span.clone()))
+}
+
+/// The universe of a function type, from the universes of its input and output.
+fn level(from: usize, to: usize) -> usize {
+    if to == 0 { 0 } else { from.max(to) }
+}
+/// Pi x:A. B, or A -> B when B doesn't use x.
+fn pi(variable: &str, from: Type, to: Type) -> Type {
+    if to.free_names().contains(variable) {
+        Type::pi(variable, from, to)
+    } else {
+        Type::arrow(from, to)
+    }
+}
+/// Normalized types are equal up to the names of bound variables, and a
+/// type in a universe is also in every universe above it.
+fn convertible(ty: &Type, expected: &Type) -> bool {
+    match (ty, expected) {
+        (Type::Universe(level), Type::Universe(expected)) => level <= expected,
+        _ => ty.alpha_eq(expected),
+    }
+}
+
+fn name(ident: &str) -> Term {
+    Term { name: Ident { span: Span::new(0..0), ident: ident.to_string() } }
+}
+fn group(statements: Statements) -> Statement {
+    let span = statements.span.clone();
+    Statement::Group(Group { statements, span })
+}
+fn abstraction(variable: &str, next: Statements) -> Statements {
+    let span = Span::new(0..0);
+    let abstraction = Statement::Abstraction(Abstraction { variable: name(variable), annotation: None, next, span: span.clone() });
+    Statements { statements: LinkedList::from([abstraction]), span }
+}
+/// The untyped term that a type is, so it can be normalized by the evaluator.
+fn to_statements(ty: &Type) -> Statements {
+    let span = Span::new(0..0);
+    let statements = match ty {
+        Type::Arrow(from, to) => {
+            let variable = fresh_name("_", &to.free_names());
+            LinkedList::from([Statement::Term(name(PI)), to_statement(from), group(abstraction(&variable, to_statements(to)))])
+        }
+        Type::Forall(variable, ty) => {
+            LinkedList::from([Statement::Term(name(PI)), to_statement(&Type::Universe(0)), group(abstraction(variable, to_statements(ty)))])
+        }
+        Type::Pi(variable, from, to) => {
+            LinkedList::from([Statement::Term(name(PI)), to_statement(from), group(abstraction(variable, to_statements(to)))])
+        }
+        Type::Apply(function, input) => {
+            let mut statements = to_statements(function).statements;
+            if statements.back().is_some_and(Statement::is_abstraction) {
+                statements = LinkedList::from([group(Statements { statements, span: span.clone() })]);
+            }
+            statements.push_back(to_statement(input));
+            statements
+        }
+        Type::Lambda(variable, _, body) => return abstraction(variable, to_statements(body)),
+        ty => LinkedList::from([to_statement(ty)]),
+    };
+    Statements { statements, span }
+}
+fn to_statement(ty: &Type) -> Statement {
+    match ty {
+        Type::Base(variable) => Statement::Term(name(variable)),
+        Type::Universe(level) => Statement::Term(name(&format!("{UNIVERSE}{level}"))),
+        Type::Variable(_) => unreachable!("only inference makes type variables"),
+        ty => group(to_statements(ty)),
+    }
+}
+/// Reads the type back from the normal form of its term.
+fn from_statements(statements: &Statements) -> Type {
+    let mut iter = statements.statements.iter();
+    let Some(head) = iter.next() else {
+        return Type::Base(String::new());
+    };
+    let head = from_statement(head);
+    let inputs: Vec<Type> = iter.map(from_statement).collect();
+    if let (Type::Base(constant), [from, Type::Lambda(variable, _, to)]) = (&head, inputs.as_slice())
+        && constant == PI {
+        return pi(variable, from.clone(), *to.clone());
+    }
+    inputs.into_iter().fold(head, Type::apply)
+}
+fn from_statement(statement: &Statement) -> Type {
+    match statement {
+        Statement::Term(term) => match term.name.ident.strip_prefix(UNIVERSE).and_then(|level| level.parse().ok()) {
+            Some(level) => Type::Universe(level),
+            None => Type::Base(term.name.ident.clone()),
+        },
+        Statement::Group(group) => from_statements(&group.statements),
+        Statement::Abstraction(abstraction) => Type::lambda(&abstraction.variable.name.ident, None, from_statements(&abstraction.next)),
+        _ => unreachable!("types are normalized without type abstractions, applications or cases"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    fn check(text: &str) -> Result<Type, ASTError> {
+        ConstructionsChecker::new().check(&Calculator::empty().parse(text).unwrap())
+    }
+    fn check_type(text: &str) -> Result<Type, ASTError> {
+        let ty = Calculator::empty().parse_type(text).unwrap();
+        ConstructionsChecker::new().check_type(&ty, &Span::new(0..text.len()))
+    }
+
+    #[test]
+    fn dependent_identity() {
+        assert_eq!(check("\\A:Type. \\x:A. x").unwrap().to_string(), "Pi A:Type. A -> A");
+        assert_eq!(check("(\\A:Type 1. \\x:A. x) Type").unwrap().to_string(), "Type -> Type");
+    }
+
+    #[test]
+    fn universes() {
+        assert_eq!(check_type("Type").unwrap().to_string(), "Type 1");
+        // Type is impredicative, the rest of the hierarchy isn't
+        assert_eq!(check_type("Pi A:Type. A -> A").unwrap().to_string(), "Type");
+        assert_eq!(check_type("Pi A:Type 1. A").unwrap().to_string(), "Type 2");
+    }
+
+    #[test]
+    fn type_is_not_in_type() {
+        let Err(ASTError::Type(msg, span)) = check("(\\A:Type. \\x:A. x) Type") else {
+            panic!("expected a universe error");
+        };
+        assert_eq!(msg, "Expected an input of type Type, found Type 1");
+        assert_eq!(span.range, 19..23);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{collections::HashMap, fmt::Display};
+
+use gelato_parser::lexer::span::Span;
+
+use crate::{ast::{Statement, Statements}, error::ASTError, expansion::SourceMap, types::Type};
+
+/// A type with its variables generalized, `∀a b. a -> b -> a`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheme {
+    pub variables: Vec<usize>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    /// A scheme that isn't polymorphic, like the type of a bound variable.
+    pub fn mono(ty: Type) -> Self {
+        Self { variables: vec![], ty }
+    }
+}
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Every variable of a scheme is quantified, so it's printed like a plain type
+        self.ty.normalized().fmt(f)
+    }
+}
+
+/// A hole that was found while inferring, with the variables that were in scope at it.
+struct Hole {
+    name: String,
+    span: Span,
+    ty: Type,
+    scope: Vec<(String, Scheme)>,
+}
+
+/// Algorithm W. Variables bound by abstractions are monomorphic, while
+/// macros are let bound: every use instantiates their scheme again.
+pub struct Inference<'a> {
+    next: usize,
+    substitution: HashMap<usize, Type>,
+    context: Vec<(String, Scheme)>,
+    /// The type variables bound by type abstractions, which are unknowns like any other.
+    type_variables: Vec<(String, Type)>,
+    /// The types of the macros that have one.
+    macros: &'a HashMap<String, Scheme>,
+    holes: Vec<Hole>,
+    /// How many of the variables in the context are bound by an abstraction, the
+    /// ones before them were bound from the outside and aren't in scope of a hole.
+    abstractions: usize,
+}
+
+impl<'a> Inference<'a> {
+    pub fn new(macros: &'a HashMap<String, Scheme>) -> Self {
+        Self { next: 0, substitution: HashMap::new(), context: vec![], type_variables: vec![], macros, holes: vec![], abstractions: 0 }
+    }
+    pub fn fresh(&mut self) -> Type {
+        self.next += 1;
+        Type::Variable(self.next - 1)
+    }
+    /// Puts a variable in scope for the statements that get inferred next.
+    pub fn bind(&mut self, name: &str, scheme: Scheme) {
+        self.context.push((name.to_string(), scheme));
+    }
+    /// Takes the variable that was bound last out of scope.
+    pub fn unbind(&mut self) {
+        self.context.pop();
+    }
+    /// Applies everything that has been learned about the variables to the type.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Variable(variable) => match self.substitution.get(variable) {
+                Some(ty) => self.resolve(ty),
+                None => ty.clone(),
+            },
+            ty => ty.map(&|ty| self.resolve(ty)),
+        }
+    }
+    fn occurs(&self, variable: usize, ty: &Type) -> bool {
+        let mut variables = vec![];
+        self.resolve(ty).collect_variables(&mut variables);
+        variables.contains(&variable)
+    }
+    /// Makes both types equal, or says why they can't be.
+    pub fn unify(&mut self, lhs: &Type, rhs: &Type) -> Result<(), String> {
+        match (self.resolve(lhs), self.resolve(rhs)) {
+            (Type::Variable(a), Type::Variable(b)) if a == b => Ok(()),
+            (Type::Variable(variable), ty) | (ty, Type::Variable(variable)) => {
+                if self.occurs(variable, &ty) {
+                    let ty = Type::arrow(Type::Variable(variable), ty).normalized();
+                    let Type::Arrow(variable, ty) = ty else { unreachable!() };
+                    return Err(format!("{variable} can't be {ty}, that type would be infinite"));
+                }
+                self.substitution.insert(variable, ty);
+                Ok(())
+            }
+            (Type::Arrow(from, to), Type::Arrow(other_from, other_to)) => {
+                self.unify(&from, &other_from)?;
+                self.unify(&to, &other_to)
+            }
+            (Type::Apply(function, input), Type::Apply(other_function, other_input)) => {
+                self.unify(&function, &other_function)?;
+                self.unify(&input, &other_input)
+            }
+            (Type::Base(a), Type::Base(b)) if a == b => Ok(()),
+            (Type::Universe(a), Type::Universe(b)) if a == b => Ok(()),
+            (lhs, rhs) => {
+                let Type::Arrow(lhs, rhs) = Type::arrow(lhs, rhs).normalized() else { unreachable!() };
+                Err(format!("{lhs} doesn't match {rhs}"))
+            }
+        }
+    }
+    /// Replaces the names of a written type that are bound by a type abstraction.
+    fn annotation(&self, ty: &Type) -> Type {
+        self.type_variables.iter().rev().fold(ty.clone(), |ty, (name, variable)| ty.instantiate(name, variable))
+    }
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: HashMap<usize, Type> = scheme.variables.iter().map(|variable| (*variable, self.fresh())).collect();
+        scheme.ty.substitute(&fresh)
+    }
+    /// Quantifies the variables of the type that aren't used by the context.
+    pub fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut bound = vec![];
+        for (_, scheme) in self.context.iter() {
+            let ty = self.resolve(&scheme.ty);
+            ty.collect_variables(&mut bound);
+        }
+        let mut variables = vec![];
+        ty.collect_variables(&mut variables);
+        variables.retain(|variable| !bound.contains(variable));
+        Scheme { variables, ty }
+    }
+    /// Describes the holes that were found, in the order they were written. Each one
+    /// gets the type that is expected of it and the variables that are in scope.
+    /// The variables of those types and of `types` are named together, so one
+    /// variable has the same name everywhere, and `types` come back with those names.
+    pub fn holes(&self, types: &[Type]) -> (Vec<Type>, Vec<String>) {
+        let types: Vec<Type> = types.iter().map(|ty| self.resolve(ty)).collect();
+        let mut holes: Vec<&Hole> = self.holes.iter().collect();
+        holes.sort_by_key(|hole| hole.span.range.start);
+        let holes = holes.into_iter().map(|hole| {
+            let mut scope: Vec<(&String, Type)> = vec![];
+            for (name, scheme) in hole.scope.iter().rev() {
+                if !scope.iter().any(|(other, _)| *other == name) {
+                    scope.push((name, self.resolve(&scheme.ty)));
+                }
+            }
+            scope.reverse();
+            (hole, self.resolve(&hole.ty), scope)
+        }).collect::<Vec<_>>();
+        let mut variables = vec![];
+        for ty in types.iter() {
+            ty.collect_variables(&mut variables);
+        }
+        for (_, ty, scope) in holes.iter() {
+            ty.collect_variables(&mut variables);
+            for (_, ty) in scope.iter() {
+                ty.collect_variables(&mut variables);
+            }
+        }
+        let names = variables.into_iter().enumerate().map(|(i, variable)| (variable, Type::Variable(i))).collect();
+        let reports = holes.into_iter().map(|(hole, ty, scope)| {
+            let mut report = format!("?{} : {}", hole.name, ty.substitute(&names));
+            for (name, ty) in scope {
+                report.push_str(&format!("\n  {name} : {}", ty.substitute(&names)));
+            }
+            report
+        }).collect();
+        (types.iter().map(|ty| ty.substitute(&names)).collect(), reports)
+    }
+    /// Infers the type of the statements. `map` tells which groups are
+    /// uses of a macro, those get the type of the macro instead.
+    pub fn infer(&mut self, statements: &Statements, map: &SourceMap) -> Result<Type, ASTError> {
+        let mut iter = statements.statements.iter();
+        let head = iter.next().ok_or(ASTError::Type("Expected a term".to_string(), statements.span.clone()))?;
+        let mut ty = self.infer_statement(head, map)?;
+        for input in iter.filter(|input| !matches!(input, Statement::TypeApplication(_))) {
+            let input_ty = self.infer_statement(input, map)?;
+            let output = self.fresh();
+            let function = self.resolve(&ty);
+            if let Err(msg) = self.unify(&ty, &Type::arrow(input_ty.clone(), output.clone())) {
+                let input_ty = self.resolve(&input_ty);
+                let msg = match function {
+                    Type::Arrow(..) | Type::Variable(_) => format!("Can't take an input of type {}: {msg}", input_ty.normalized()),
+                    _ => format!("This has type {}, it can't be applied to anything", function.normalized()),
+                };
+                return Err(ASTError::Type(msg, input.span().clone()));
+            }
+            ty = output;
+        }
+        Ok(self.resolve(&ty))
+    }
+    fn infer_statement(&mut self, statement: &Statement, map: &SourceMap) -> Result<Type, ASTError> {
+        match statement {
+            // Types are inferred for the term with its type abstractions and applications erased
+            Statement::TypeAbstraction(abstraction) => {
+                let variable = self.fresh();
+                self.type_variables.push((abstraction.variable.ident.clone(), variable));
+                let ty = self.infer(&abstraction.next, map);
+                self.type_variables.pop();
+                ty
+            }
+            Statement::TypeApplication(application) => {
+                Err(ASTError::Type("A type application needs a term before it".to_string(), application.span.clone()))
+            }
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+            // A hole could be anything, what it has to be is found out from where it is
+            Statement::Hole(hole) => {
+                let ty = self.fresh();
+                self.holes.push(Hole { name: hole.name.clone(), span: hole.span.clone(), ty: ty.clone(), scope: self.context[self.context.len() - self.abstractions..].to_vec() });
+                Ok(ty)
+            }
+            Statement::Term(term) => {
+                let scheme = self.context.iter().rev()
+                    .find(|(name, _)| *name == term.name.ident)
+                    .map(|(_, scheme)| scheme.clone())
+                    .ok_or(ASTError::Type(format!("'{}' is not bound", term.name.ident), term.name.span.clone()))?;
+                Ok(self.instantiate(&scheme))
+            }
+            Statement::Group(group) => {
+                let scheme = map.expansion_of(&group.span).and_then(|expansion| self.macros.get(&expansion.name));
+                if let Some(scheme) = scheme {
+                    let scheme = scheme.clone();
+                    return Ok(self.instantiate(&scheme));
+                }
+                self.infer(&group.statements, map)
+            }
+            Statement::Abstraction(abstraction) => {
+                let variable = match &abstraction.annotation {
+                    Some(annotation) => self.annotation(annotation),
+                    None => self.fresh(),
+                };
+                self.bind(&abstraction.variable.name.ident, Scheme::mono(variable.clone()));
+                self.abstractions += 1;
+                let body = self.infer(&abstraction.next, map);
+                self.abstractions -= 1;
+                self.context.pop();
+                Ok(Type::arrow(self.resolve(&variable), body?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    fn infer(text: &str) -> Result<Type, ASTError> {
+        let macros = HashMap::new();
+        let statements = Calculator::empty().parse(text).unwrap();
+        Inference::new(&macros).infer(&statements, &SourceMap::default()).map(|ty| ty.normalized())
+    }
+
+    #[test]
+    fn infers_the_most_general_type() {
+        assert_eq!(infer("\\f x. f (f x)").unwrap().to_string(), "(a -> a) -> a -> a");
+        assert_eq!(infer("\\x y. x").unwrap().to_string(), "a -> b -> a");
+    }
+
+    #[test]
+    fn self_application_is_an_infinite_type() {
+        let Err(ASTError::Type(msg, span)) = infer("\\x. x x") else {
+            panic!("expected a type error");
+        };
+        assert!(msg.contains("that type would be infinite"), "{msg}");
+        assert_eq!(span.range, 6..7);
+    }
+
+    #[test]
+    fn unbound_variable() {
+        assert!(matches!(infer("y"), Err(ASTError::Type(msg, _)) if msg == "'y' is not bound"));
+    }
+
+    #[test]
+    fn holes_and_the_term_share_names() {
+        let macros = HashMap::new();
+        let statements = Calculator::empty().parse("\\f x. ?h (f x)").unwrap();
+        let mut inference = Inference::new(&macros);
+        let ty = inference.infer(&statements, &SourceMap::default()).unwrap();
+        let (types, holes) = inference.holes(&[ty]);
+        assert_eq!(types[0].to_string(), "(a -> b) -> a -> c");
+        assert_eq!(holes, ["?h : b -> c\n  f : a -> b\n  x : a"]);
+    }
+}
@@ -0,0 +1,466 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+
+use gelato_parser::lexer::{literal::LitKind, span::Span, tokens::{Token, Tokens}};
+
+use crate::{ast::{FromTokens, fresh_name}, error::ASTError, expansion::{Definition, macro_name}, operators::Operators};
+
+pub mod simple;
+pub mod inference;
+pub mod system_f;
+pub mod constructions;
+pub mod usage;
+pub mod synthesis;
+
+/// Which type system terms are checked with before they run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mode {
+    /// Anything that parses runs.
+    #[default]
+    Untyped,
+    /// The simply typed lambda calculus, every binder needs an annotation.
+    Simple,
+    /// Hindley-Milner, the types are inferred and annotations are optional.
+    Inferred,
+    /// System F, polymorphism is written with type abstractions and applications.
+    SystemF,
+    /// The Calculus of Constructions, types can depend on terms.
+    Constructions,
+}
+
+/// T ::= <ident> | T -> T | forall <ident>... . T | (T) |
+/// Type [<number>] | Pi <ident>:T. T | T T | \<ident>[:T]. T | #<ident>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// A base type, or a variable of the Calculus of Constructions.
+    Base(String),
+    Arrow(Box<Type>, Box<Type>),
+    /// A type that is still unknown, only made by inference.
+    Variable(usize),
+    /// forall a. T, the type of a type abstraction. `a` is a Base inside of T.
+    Forall(String, Box<Type>),
+    /// Type n, the type of the types in Type (n - 1). Type is Type 0.
+    Universe(usize),
+    /// Pi x:A. B, a function type whose output type depends on the input x.
+    Pi(String, Box<Type>, Box<Type>),
+    /// F X, a type applied to a type or to a term.
+    Apply(Box<Type>, Box<Type>),
+    /// \x:A. T, a function inside of a type. Normalized types don't have annotations.
+    Lambda(String, Option<Box<Type>>, Box<Type>),
+}
+
+/// Name of the i'th type variable, following a, b, ..., z, aa, ab...
+pub fn variable_name(mut i: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'a' + (i % 26) as u8) as char);
+        if i < 26 {
+            return name;
+        }
+        i = i / 26 - 1;
+    }
+}
+
+impl Type {
+    pub fn arrow(from: Type, to: Type) -> Self {
+        Type::Arrow(Box::new(from), Box::new(to))
+    }
+    pub fn forall(variable: &str, ty: Type) -> Self {
+        Type::Forall(variable.to_string(), Box::new(ty))
+    }
+    pub fn pi(variable: &str, from: Type, to: Type) -> Self {
+        Type::Pi(variable.to_string(), Box::new(from), Box::new(to))
+    }
+    pub fn apply(function: Type, input: Type) -> Self {
+        Type::Apply(Box::new(function), Box::new(input))
+    }
+    pub fn lambda(variable: &str, annotation: Option<Type>, body: Type) -> Self {
+        Type::Lambda(variable.to_string(), annotation.map(Box::new), Box::new(body))
+    }
+    /// The type can be printed anywhere without parenthesis.
+    pub fn is_atom(&self) -> bool {
+        matches!(self, Type::Base(_) | Type::Variable(_) | Type::Universe(0))
+    }
+    /// The type extends as far to the right as it can, so it needs
+    /// parenthesis in front of anything.
+    fn is_open(&self) -> bool {
+        matches!(self, Type::Arrow(..) | Type::Forall(..) | Type::Pi(..) | Type::Lambda(..))
+    }
+    /// The type variables in order of appearance, without repeating any.
+    pub fn collect_variables(&self, variables: &mut Vec<usize>) {
+        match self {
+            Type::Variable(variable) => {
+                if !variables.contains(variable) {
+                    variables.push(*variable);
+                }
+            }
+            Type::Arrow(from, to) | Type::Pi(_, from, to) | Type::Apply(from, to) => {
+                from.collect_variables(variables);
+                to.collect_variables(variables);
+            }
+            Type::Forall(_, ty) => ty.collect_variables(variables),
+            Type::Lambda(_, annotation, body) => {
+                if let Some(annotation) = annotation {
+                    annotation.collect_variables(variables);
+                }
+                body.collect_variables(variables);
+            }
+            Type::Base(_) | Type::Universe(_) => {}
+        }
+    }
+    /// Names of the base types that aren't bound by a forall, pi or lambda.
+    pub fn free_names(&self) -> HashSet<String> {
+        match self {
+            Type::Base(name) => HashSet::from([name.clone()]),
+            Type::Arrow(from, to) | Type::Apply(from, to) => from.free_names().union(&to.free_names()).cloned().collect(),
+            Type::Forall(variable, ty) => {
+                let mut names = ty.free_names();
+                names.remove(variable);
+                names
+            }
+            Type::Pi(variable, from, to) => {
+                let mut names = to.free_names();
+                names.remove(variable);
+                names.extend(from.free_names());
+                names
+            }
+            Type::Lambda(variable, annotation, body) => {
+                let mut names = body.free_names();
+                names.remove(variable);
+                if let Some(annotation) = annotation {
+                    names.extend(annotation.free_names());
+                }
+                names
+            }
+            Type::Variable(_) | Type::Universe(_) => HashSet::new(),
+        }
+    }
+    /// Every name in the type, free or bound.
+    pub fn names(&self) -> HashSet<String> {
+        match self {
+            Type::Base(name) => HashSet::from([name.clone()]),
+            Type::Arrow(from, to) | Type::Apply(from, to) => from.names().union(&to.names()).cloned().collect(),
+            Type::Forall(variable, ty) => {
+                let mut names = ty.names();
+                names.insert(variable.clone());
+                names
+            }
+            Type::Pi(variable, from, to) => {
+                let mut names = from.names();
+                names.extend(to.names());
+                names.insert(variable.clone());
+                names
+            }
+            Type::Lambda(variable, annotation, body) => {
+                let mut names = body.names();
+                names.insert(variable.clone());
+                if let Some(annotation) = annotation {
+                    names.extend(annotation.names());
+                }
+                names
+            }
+            Type::Variable(_) | Type::Universe(_) => HashSet::new(),
+        }
+    }
+    /// Replaces the free uses of the type variable `name` with `with`,
+    /// renaming the binders that would capture a name of `with`.
+    pub fn instantiate(&self, name: &str, with: &Type) -> Type {
+        // The variable a binder binds, with its body renamed if it would capture something
+        let bind = |variable: &String, body: &Type| -> (String, Type) {
+            let free = with.free_names();
+            if !free.contains(variable) {
+                return (variable.clone(), body.instantiate(name, with));
+            }
+            let mut taken = body.names();
+            taken.extend(free);
+            let fresh = fresh_name(variable, &taken);
+            let body = body.instantiate(variable, &Type::Base(fresh.clone()));
+            (fresh, body.instantiate(name, with))
+        };
+        match self {
+            Type::Base(base) if base == name => with.clone(),
+            Type::Base(_) | Type::Variable(_) | Type::Universe(_) => self.clone(),
+            Type::Arrow(from, to) => Type::arrow(from.instantiate(name, with), to.instantiate(name, with)),
+            Type::Apply(function, input) => Type::apply(function.instantiate(name, with), input.instantiate(name, with)),
+            Type::Forall(variable, _) if variable == name => self.clone(),
+            Type::Forall(variable, ty) => {
+                let (variable, ty) = bind(variable, ty);
+                Type::forall(&variable, ty)
+            }
+            Type::Pi(variable, from, to) => {
+                let from = from.instantiate(name, with);
+                if variable == name {
+                    return Type::pi(variable, from, *to.clone());
+                }
+                let (variable, to) = bind(variable, to);
+                Type::pi(&variable, from, to)
+            }
+            Type::Lambda(variable, annotation, body) => {
+                let annotation = annotation.as_ref().map(|annotation| annotation.instantiate(name, with));
+                if variable == name {
+                    return Type::lambda(variable, annotation, *body.clone());
+                }
+                let (variable, body) = bind(variable, body);
+                Type::lambda(&variable, annotation, body)
+            }
+        }
+    }
+    /// Equality that doesn't care about the names of bound variables,
+    /// so `forall a. a` is `forall b. b`. Annotations of lambdas are ignored.
+    pub fn alpha_eq(&self, other: &Type) -> bool {
+        self.alpha_eq_in(other, &mut vec![])
+    }
+    fn alpha_eq_in(&self, other: &Type, bound: &mut Vec<(String, String)>) -> bool {
+        fn under(a: &str, b: &str, lhs: &Type, rhs: &Type, bound: &mut Vec<(String, String)>) -> bool {
+            bound.push((a.to_string(), b.to_string()));
+            let eq = lhs.alpha_eq_in(rhs, bound);
+            bound.pop();
+            eq
+        }
+        match (self, other) {
+            (Type::Base(a), Type::Base(b)) => {
+                // Both have to be bound by the same binder, or both be free
+                let lhs = bound.iter().rposition(|(lhs, _)| lhs == a);
+                let rhs = bound.iter().rposition(|(_, rhs)| rhs == b);
+                lhs == rhs && (lhs.is_some() || a == b)
+            }
+            (Type::Arrow(from, to), Type::Arrow(other_from, other_to))
+            | (Type::Apply(from, to), Type::Apply(other_from, other_to)) => {
+                from.alpha_eq_in(other_from, bound) && to.alpha_eq_in(other_to, bound)
+            }
+            (Type::Forall(a, ty), Type::Forall(b, other)) => under(a, b, ty, other, bound),
+            (Type::Pi(a, from, to), Type::Pi(b, other_from, other_to)) => {
+                from.alpha_eq_in(other_from, bound) && under(a, b, to, other_to, bound)
+            }
+            (Type::Lambda(a, _, body), Type::Lambda(b, _, other)) => under(a, b, body, other, bound),
+            (Type::Variable(a), Type::Variable(b)) => a == b,
+            (Type::Universe(a), Type::Universe(b)) => a == b,
+            _ => false,
+        }
+    }
+    /// Rebuilds the type with `f` applied to the types right inside of it.
+    pub fn map(&self, f: &impl Fn(&Type) -> Type) -> Type {
+        match self {
+            Type::Arrow(from, to) => Type::arrow(f(from), f(to)),
+            Type::Forall(variable, ty) => Type::forall(variable, f(ty)),
+            Type::Pi(variable, from, to) => Type::pi(variable, f(from), f(to)),
+            Type::Apply(function, input) => Type::apply(f(function), f(input)),
+            Type::Lambda(variable, annotation, body) => Type::lambda(variable, annotation.as_deref().map(f), f(body)),
+            Type::Base(_) | Type::Variable(_) | Type::Universe(_) => self.clone(),
+        }
+    }
+    /// Replaces the type variables that are in `with`.
+    pub fn substitute(&self, with: &HashMap<usize, Type>) -> Type {
+        match self {
+            Type::Variable(variable) => with.get(variable).cloned().unwrap_or(self.clone()),
+            ty => ty.map(&|ty| ty.substitute(with)),
+        }
+    }
+    /// Numbers the type variables from 0 in order of appearance,
+    /// so they print as a, b, c...
+    pub fn normalized(&self) -> Type {
+        let mut variables = vec![];
+        self.collect_variables(&mut variables);
+        let with = variables.into_iter().enumerate().map(|(i, variable)| (variable, Type::Variable(i))).collect();
+        self.substitute(&with)
+    }
+    /// Replaces the macros in the type with the types they are defined as.
+    pub fn expand_macros(&self, macros: &HashMap<String, Definition>) -> Result<Type, String> {
+        self.expand_macros_from(macros, &mut vec![])
+    }
+    fn expand_macros_from(&self, macros: &HashMap<String, Definition>, expanding: &mut Vec<String>) -> Result<Type, String> {
+        let mut ty = self.clone();
+        let mut names: Vec<String> = self.free_names().into_iter().filter(|name| name.starts_with('#')).collect();
+        names.sort();
+        for name in names {
+            let Some(definition) = macros.get(&name) else {
+                return Err(format!("'{name}' is not defined"));
+            };
+            let Some(with) = definition.ty.as_ref().filter(|_| definition.parameters.is_empty()) else {
+                return Err(format!("'{name}' can't be used as a type"));
+            };
+            if expanding.contains(&name) {
+                return Err(format!("'{name}' is defined in terms of itself"));
+            }
+            expanding.push(name.clone());
+            let with = with.expand_macros_from(macros, expanding)?;
+            expanding.pop();
+            ty = ty.instantiate(&name, &with);
+        }
+        Ok(ty)
+    }
+    /// Consumes a `->`, which is either one declared operator or a '-' and a '>'.
+    pub fn next_is_arrow(tokens: &mut Tokens) -> bool {
+        match tokens.tokens.get(tokens.next) {
+            Some(token) if token.is_punct_subset("->") => {
+                tokens.next += 1;
+                true
+            }
+            Some(token) if token.is_punct_subset("-") && tokens.tokens.get(tokens.next + 1).is_some_and(|token| token.is_punct_subset(">")) => {
+                tokens.next += 2;
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Parses the type of a binder, `\x:A y:B.`. Type applications need parenthesis
+    /// there, otherwise the next binder would be taken as an input.
+    pub fn annotation_from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        Self::parse(tokens, operators, false)
+    }
+    fn parse(tokens: &mut Tokens, operators: &Operators, applications: bool) -> Result<Self, ASTError> {
+        let from = if applications {
+            Self::application_from_tokens(tokens, operators)?
+        } else {
+            Self::atom_from_tokens(tokens, operators)?
+        };
+        if !Self::next_is_arrow(tokens) {
+            return Ok(from);
+        }
+        // Arrows are right associative, A -> B -> C is A -> (B -> C)
+        let to = Self::parse(tokens, operators, applications)?;
+        Ok(Type::arrow(from, to))
+    }
+    /// F X Y, which is (F X) Y.
+    fn application_from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        let mut ty = Self::atom_from_tokens(tokens, operators)?;
+        while let Some(token) = tokens.tokens.get(tokens.next) {
+            let input = match token {
+                Token::Ident(ident) => !["forall", "Pi"].contains(&ident.ident.as_str()),
+                Token::Group(group) => group.open == '(' || group.open == '[',
+                Token::Punct(punct) => punct.punct == "#",
+                _ => false,
+            };
+            if !input {
+                break;
+            }
+            ty = Type::apply(ty, Self::atom_from_tokens(tokens, operators)?);
+        }
+        Ok(ty)
+    }
+    /// Parses the variables of `forall a b.` and `\x:A y:B.`, up to the '.'. Like the
+    /// annotations of terms, an annotation that is an application needs parenthesis.
+    fn binders_from_tokens(keyword: &Span, tokens: &mut Tokens, operators: &Operators, annotations: bool) -> Result<Vec<(String, Option<Type>)>, ASTError> {
+        let mut variables = vec![];
+        loop {
+            let end = Span::new(keyword.range.end..keyword.range.end);
+            let token = tokens.next().ok_or(ASTError::Syntax("Expected a variable".to_string(), end))?;
+            if token.is_punct_subset(".") && !variables.is_empty() {
+                return Ok(variables);
+            }
+            let Some(variable) = token.get_ident() else {
+                return Err(ASTError::Syntax("Expected a variable".to_string(), token.span().clone()));
+            };
+            let annotation = if annotations && tokens.tokens.get(tokens.next).is_some_and(|token| token.is_punct_subset(":")) {
+                tokens.next += 1;
+                Some(Self::parse(tokens, operators, false).map_err(|err| match err {
+                    ASTError::EmptyTokenList => ASTError::Syntax("Expected a type".to_string(), variable.span.clone()),
+                    err => err,
+                })?)
+            } else {
+                None
+            };
+            variables.push((variable.ident, annotation));
+        }
+    }
+    /// Parses the type after the '.' of a binder.
+    fn body_from_tokens(keyword: &Span, tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        Self::from_tokens(tokens, operators).map_err(|err| match err {
+            ASTError::EmptyTokenList => ASTError::Syntax("Expected a type".to_string(), keyword.clone()),
+            err => err,
+        })
+    }
+    fn atom_from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        let token = tokens.next().ok_or(ASTError::EmptyTokenList)?;
+        match token {
+            Token::Ident(ident) if ident.ident == "forall" => {
+                // forall a b. T is short for forall a. forall b. T
+                let mut variables = Self::binders_from_tokens(&ident.span, tokens, operators, false)?;
+                let mut ty = Self::body_from_tokens(&ident.span, tokens, operators)?;
+                while let Some((variable, _)) = variables.pop() {
+                    ty = Type::forall(&variable, ty);
+                }
+                Ok(ty)
+            }
+            Token::Ident(ident) if ident.ident == "Pi" => {
+                let mut variables = Self::binders_from_tokens(&ident.span, tokens, operators, true)?;
+                let mut ty = Self::body_from_tokens(&ident.span, tokens, operators)?;
+                while let Some((variable, annotation)) = variables.pop() {
+                    let Some(annotation) = annotation else {
+                        return Err(ASTError::Syntax(format!("'{variable}' needs a type annotation"), ident.span.clone()));
+                    };
+                    ty = Type::pi(&variable, annotation, ty);
+                }
+                Ok(ty)
+            }
+            Token::Ident(ident) if ident.ident == "Type" => {
+                // Type 1 is a universe, Type alone is Type 0
+                let Some(level) = tokens.tokens.get(tokens.next).and_then(Token::get_literal).filter(|literal| literal.kind == LitKind::Number) else {
+                    return Ok(Type::Universe(0));
+                };
+                tokens.next += 1;
+                let level = level.value.parse().map_err(|_| ASTError::Syntax(format!("Invalid universe '{}'", level.value), level.span.clone()))?;
+                Ok(Type::Universe(level))
+            }
+            Token::Ident(ident) => Ok(Type::Base(ident.ident)),
+            // A macro, it stays a name until the macros get expanded
+            Token::Punct(punct) if punct.punct == "#" => match macro_name(&tokens.tokens, tokens.next, punct.span.range.end) {
+                Some((name, next, _)) => {
+                    tokens.next = next;
+                    Ok(Type::Base(name))
+                }
+                None => Err(ASTError::Syntax("Expected the name of a macro".to_string(), punct.span.clone())),
+            },
+            Token::Punct(punct) if punct.punct == "\\" => {
+                let mut variables = Self::binders_from_tokens(&punct.span, tokens, operators, true)?;
+                let mut ty = Self::body_from_tokens(&punct.span, tokens, operators)?;
+                while let Some((variable, annotation)) = variables.pop() {
+                    ty = Type::lambda(&variable, annotation, ty);
+                }
+                Ok(ty)
+            }
+            Token::Group(group) if group.close == '\0' => Err(ASTError::Syntax(format!("Unclosed '{}'", group.open), group.span)),
+            Token::Group(mut group) if group.open == '(' || group.open == '[' => {
+                let ty = Self::from_tokens(&mut group.tokens, operators)?;
+                if let Some(token) = group.tokens.next() {
+                    return Err(ASTError::Syntax("Expected the type to end".to_string(), token.span().clone()));
+                }
+                Ok(ty)
+            }
+            token => Err(ASTError::Syntax("Expected a type".to_string(), token.span().clone())),
+        }
+    }
+}
+impl FromTokens for Type {
+    fn from_tokens(tokens: &mut Tokens, operators: &Operators) -> Result<Self, ASTError> {
+        Self::parse(tokens, operators, true)
+    }
+}
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parenthesized = |ty: &Type| if ty.is_open() { format!("({ty})") } else { ty.to_string() };
+        match self {
+            Type::Base(name) => f.write_str(name),
+            Type::Variable(variable) => f.write_str(&variable_name(*variable)),
+            Type::Universe(0) => f.write_str("Type"),
+            Type::Universe(level) => write!(f, "Type {level}"),
+            Type::Arrow(from, to) => write!(f, "{} -> {to}", parenthesized(from)),
+            Type::Forall(variable, ty) => {
+                // forall a. forall b. T is printed as forall a b. T
+                write!(f, "forall {variable}")?;
+                let mut ty = ty;
+                while let Type::Forall(variable, inner) = ty.as_ref() {
+                    write!(f, " {variable}")?;
+                    ty = inner;
+                }
+                write!(f, ". {ty}")
+            }
+            Type::Pi(variable, from, to) if from.is_atom() => write!(f, "Pi {variable}:{from}. {to}"),
+            Type::Pi(variable, from, to) => write!(f, "Pi {variable}:({from}). {to}"),
+            Type::Apply(function, input) if input.is_atom() => write!(f, "{} {input}", parenthesized(function)),
+            Type::Apply(function, input) => write!(f, "{} ({input})", parenthesized(function)),
+            Type::Lambda(variable, Some(annotation), body) if annotation.is_atom() => write!(f, "\\{variable}:{annotation}. {body}"),
+            Type::Lambda(variable, Some(annotation), body) => write!(f, "\\{variable}:({annotation}). {body}"),
+            Type::Lambda(variable, None, body) => write!(f, "\\{variable}. {body}"),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{ast::{Statement, Statements}, error::ASTError, types::Type};
+
+/// Checks terms of the simply typed lambda calculus. The context holds the
+/// type of every variable in scope, the innermost last.
+pub struct SimpleChecker {
+    context: Vec<(String, Type)>,
+}
+
+impl SimpleChecker {
+    pub fn new() -> Self {
+        Self { context: vec![] }
+    }
+    /// Finds the type of the statements, or the first place they go wrong.
+    pub fn check(&mut self, statements: &Statements) -> Result<Type, ASTError> {
+        let mut iter = statements.statements.iter();
+        let head = iter.next().ok_or(ASTError::Type("Expected a term".to_string(), statements.span.clone()))?;
+        let mut ty = self.check_statement(head)?;
+        let mut function = head.span().clone();
+        for input in iter {
+            let input_ty = self.check_statement(input)?;
+            let Type::Arrow(from, to) = ty else {
+                return Err(ASTError::Type(format!("This has type {ty}, it can't be applied to anything"), function));
+            };
+            if *from != input_ty {
+                return Err(ASTError::Type(format!("Expected an input of type {from}, found {input_ty}"), input.span().clone()));
+            }
+            ty = *to;
+            function = function.join(input.span());
+        }
+        Ok(ty)
+    }
+    fn check_statement(&mut self, statement: &Statement) -> Result<Type, ASTError> {
+        match statement {
+            Statement::Term(term) => {
+                self.context.iter().rev()
+                    .find(|(name, _)| *name == term.name.ident)
+                    .map(|(_, ty)| ty.clone())
+                    .ok_or(ASTError::Type(format!("'{}' is not bound", term.name.ident), term.name.span.clone()))
+            }
+            Statement::Group(group) => self.check(&group.statements),
+            Statement::TypeAbstraction(abstraction) => {
+                Err(ASTError::Type("Type abstractions need mode(systemf)".to_string(), abstraction.span.clone()))
+            }
+            Statement::TypeApplication(application) => {
+                Err(ASTError::Type("Type applications need mode(systemf)".to_string(), application.span.clone()))
+            }
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+            Statement::Hole(hole) => {
+                Err(ASTError::Type("Holes are filled in with mode(infer)".to_string(), hole.span.clone()))
+            }
+            Statement::Abstraction(abstraction) => {
+                let variable = &abstraction.variable.name;
+                let Some(annotation) = abstraction.annotation.clone() else {
+                    return Err(ASTError::Type(format!("'{}' needs a type annotation", variable.ident), variable.span.clone()));
+                };
+                self.context.push((variable.ident.clone(), annotation.clone()));
+                let body = self.check(&abstraction.next);
+                self.context.pop();
+                Ok(Type::arrow(annotation, body?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::calculator::Calculator, error::ASTError};
+
+    use super::*;
+
+    fn check(text: &str) -> Result<Type, ASTError> {
+        SimpleChecker::new().check(&Calculator::empty().parse(text).unwrap())
+    }
+
+    #[test]
+    fn annotated_application() {
+        assert_eq!(check("\\f:(A -> B) x:A. f x").unwrap().to_string(), "(A -> B) -> A -> B");
+    }
+
+    #[test]
+    fn input_of_the_wrong_type() {
+        let Err(ASTError::Type(msg, span)) = check("(\\x:A. x) (\\y:B. y)") else {
+            panic!("expected a type error");
+        };
+        assert_eq!(msg, "Expected an input of type A, found B -> B");
+        assert_eq!(span.range, 10..19);
+    }
+
+    #[test]
+    fn missing_annotation() {
+        assert!(matches!(check("\\x. x"), Err(ASTError::Type(msg, _)) if msg == "'x' needs a type annotation"));
+    }
+}
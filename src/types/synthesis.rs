@@ -0,0 +1,249 @@
+use std::collections::{HashSet, LinkedList};
+
+use gelato_parser::lexer::{ident::Ident, span::Span};
+
+use crate::{ast::{Abstraction, Group, Statement, Statements, Term, fresh_name}, types::Type};
+
+/// The size of the largest term that gets searched for, where
+/// every binder and every use of a variable counts once.
+pub const MAX_SIZE: usize = 20;
+
+/// A term in β-normal η-short form, `λbinders. head arguments...`, where the head
+/// is a variable. It isn't `λx. M x`, which is the smaller `M` written longer.
+#[derive(Clone)]
+struct Normal {
+    binders: Vec<(String, Type)>,
+    head: String,
+    arguments: Vec<Normal>,
+}
+
+impl Normal {
+    /// Whether the variable is used in the term.
+    fn uses(&self, variable: &str) -> bool {
+        self.head == variable || self.arguments.iter().any(|argument| argument.uses(variable))
+    }
+    /// Whether the term is just the variable.
+    fn is_variable(&self, variable: &str) -> bool {
+        self.binders.is_empty() && self.arguments.is_empty() && self.head == variable
+    }
+    fn statements(&self) -> Statements {
+        let span = Span::new(0..0);
+        let name = |ident: &str| Term { name: Ident { span: span.clone(), ident: ident.to_string() } };
+        let mut statements = LinkedList::from([Statement::Term(name(&self.head))]);
+        for argument in self.arguments.iter() {
+            if argument.binders.is_empty() && argument.arguments.is_empty() {
+                statements.push_back(Statement::Term(name(&argument.head)));
+            } else {
+                statements.push_back(Statement::Group(Group { statements: argument.statements(), span: span.clone() }));
+            }
+        }
+        let mut next = Statements { statements, span: span.clone() };
+        for (variable, ty) in self.binders.iter().rev() {
+            let abstraction = Statement::Abstraction(Abstraction { variable: name(variable), annotation: Some(ty.clone()), next, span: span.clone() });
+            next = Statements { statements: LinkedList::from([abstraction]), span: span.clone() };
+        }
+        next
+    }
+}
+
+/// Finds up to `count` terms of the type in normal form, the smallest first.
+/// By the Curry-Howard correspondence this is proof search in intuitionistic
+/// propositional logic, where the arrows are implications. The type gets checked
+/// for a proof first, so `None` means that the type has no terms at all.
+pub fn synthesize(ty: &Type, count: usize) -> Result<Option<Vec<Statements>>, String> {
+    let mut ty = ty;
+    // forall a. T has a term when T has one for a type a that nothing is known about
+    while let Type::Forall(_, body) = ty {
+        ty = body;
+    }
+    if !is_propositional(ty) {
+        return Err(format!("{ty} isn't made of arrows and type variables"));
+    }
+    if !provable(ty, &mut vec![], &mut vec![]) {
+        return Ok(None);
+    }
+    let mut found = vec![];
+    for size in 1..=MAX_SIZE {
+        found.extend(terms(ty, &mut vec![], size).iter().map(Normal::statements));
+        if found.len() >= count {
+            break;
+        }
+    }
+    found.truncate(count);
+    Ok(Some(found))
+}
+
+fn is_propositional(ty: &Type) -> bool {
+    match ty {
+        Type::Base(_) | Type::Variable(_) => true,
+        Type::Arrow(from, to) => is_propositional(from) && is_propositional(to),
+        _ => false,
+    }
+}
+/// `A -> B -> c` without its first `count` inputs.
+fn drop_inputs(ty: &Type, count: usize) -> &Type {
+    (0..count).fold(ty, |ty, _| match ty {
+        Type::Arrow(_, to) => to,
+        ty => ty,
+    })
+}
+/// Splits `A -> B -> c` into its inputs `[A, B]` and the type variable `c`.
+fn split(ty: &Type) -> (Vec<&Type>, &Type) {
+    let mut inputs = vec![];
+    let mut ty = ty;
+    while let Type::Arrow(from, to) = ty {
+        inputs.push(&**from);
+        ty = to;
+    }
+    (inputs, ty)
+}
+/// Searches for a proof, giving up on a goal that is already being proven from the
+/// same assumptions further up. There are only finitely many of those, so it ends.
+fn provable(goal: &Type, assumptions: &mut Vec<Type>, seen: &mut Vec<(Vec<Type>, Type)>) -> bool {
+    let (inputs, atom) = split(goal);
+    let before = assumptions.len();
+    for input in inputs {
+        if !assumptions.contains(input) {
+            assumptions.push(input.clone());
+        }
+    }
+    let repeated = seen.iter().any(|(other, other_atom)| {
+        other_atom == atom && other.len() == assumptions.len() && other.iter().all(|ty| assumptions.contains(ty))
+    });
+    let proven = !repeated && {
+        seen.push((assumptions.clone(), atom.clone()));
+        let proven = assumptions.clone().iter().any(|assumption| {
+            let (premises, conclusion) = split(assumption);
+            conclusion == atom && premises.iter().all(|premise| provable(premise, assumptions, seen))
+        });
+        seen.pop();
+        proven
+    };
+    assumptions.truncate(before);
+    proven
+}
+/// Names for a new variable of the type, `f` for functions and `x` for the rest.
+fn binder_name(ty: &Type, taken: &HashSet<String>) -> String {
+    let names: &[&str] = match ty {
+        Type::Arrow(..) => &["f", "g", "h", "k"],
+        _ => &["x", "y", "z", "w"],
+    };
+    names.iter().find(|name| !taken.contains(**name)).map_or_else(|| fresh_name(names[0], taken), |name| name.to_string())
+}
+/// Every term of the type that has exactly `size`, with the variables of `context` in scope.
+/// The term takes some of the inputs of the type, and the head gives back the rest of it.
+fn terms(goal: &Type, context: &mut Vec<(String, Type)>, size: usize) -> Vec<Normal> {
+    if size == 0 {
+        return vec![];
+    }
+    let (inputs, _) = split(goal);
+    let mut found = vec![];
+    for taken_inputs in 0..=inputs.len().min(size.saturating_sub(1)) {
+        let mut taken: HashSet<String> = context.iter().map(|(name, _)| name.clone()).collect();
+        let binders: Vec<(String, Type)> = inputs[..taken_inputs].iter().map(|input| {
+            let name = binder_name(input, &taken);
+            taken.insert(name.clone());
+            (name, (*input).clone())
+        }).collect();
+        let rest = drop_inputs(goal, taken_inputs);
+        context.extend(binders.iter().cloned());
+        let remaining = size - binders.len() - 1;
+        // The innermost variables first, which keeps the answers in a natural order
+        for (head, ty) in context.clone().iter().rev() {
+            let (premises, _) = split(ty);
+            for applied in 0..=premises.len() {
+                if drop_inputs(ty, applied) != rest {
+                    continue;
+                }
+                for sizes in compositions(remaining, applied) {
+                    let mut partial: Vec<Vec<Normal>> = vec![vec![]];
+                    for (premise, size) in premises.iter().zip(sizes) {
+                        let options = terms(premise, context, size);
+                        partial = partial.into_iter().flat_map(|done| options.iter().map(move |option| {
+                            let mut done = done.clone();
+                            done.push(option.clone());
+                            done
+                        })).collect();
+                        if partial.is_empty() {
+                            break;
+                        }
+                    }
+                    let term = |arguments| Normal { binders: binders.clone(), head: head.clone(), arguments };
+                    found.extend(partial.into_iter().map(term).filter(|term| !eta_reducible(term)));
+                }
+            }
+        }
+        context.truncate(context.len() - binders.len());
+    }
+    found
+}
+/// Whether the term is `λ... x. M x` with `x` not used by `M`.
+fn eta_reducible(term: &Normal) -> bool {
+    let (Some((last, _)), Some((argument, others))) = (term.binders.last(), term.arguments.split_last()) else {
+        return false;
+    };
+    argument.is_variable(last) && term.head != *last && !others.iter().any(|other| other.uses(last))
+}
+/// Every way to split `total` into `parts` sizes that are at least 1.
+fn compositions(total: usize, parts: usize) -> Vec<Vec<usize>> {
+    if parts == 0 {
+        return if total == 0 { vec![vec![]] } else { vec![] };
+    }
+    (1..=total.saturating_sub(parts - 1)).flat_map(|first| {
+        compositions(total - first, parts - 1).into_iter().map(move |mut rest| {
+            rest.insert(0, first);
+            rest
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::calculator::Calculator, printer::PrintOptions, types::simple::SimpleChecker};
+
+    use super::*;
+
+    fn synthesize(text: &str, count: usize) -> Result<Option<Vec<Statements>>, String> {
+        super::synthesize(&Calculator::empty().parse_type(text).unwrap(), count)
+    }
+
+    #[test]
+    fn composition() {
+        let terms = synthesize("(a -> b) -> (b -> c) -> a -> c", 5).unwrap().unwrap();
+        let printed: Vec<String> = terms.iter().map(|term| PrintOptions::default().statements(term)).collect();
+        assert_eq!(printed, ["λf:(a -> b) g:(b -> c) x:a.g (f x)"]);
+    }
+
+    #[test]
+    fn terms_have_the_type() {
+        let ty = Calculator::empty().parse_type("(a -> a) -> a -> a").unwrap();
+        let terms = super::synthesize(&ty, 3).unwrap().unwrap();
+        assert_eq!(terms.len(), 3);
+        for term in terms {
+            assert_eq!(SimpleChecker::new().check(&term).unwrap(), ty);
+        }
+    }
+
+    #[test]
+    fn peirce_is_not_provable() {
+        assert!(synthesize("((a -> b) -> a) -> a", 5).unwrap().is_none());
+        assert!(synthesize("a -> b", 5).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_propositions() {
+        assert!(synthesize("Type -> Type", 5).is_err());
+    }
+
+    #[test]
+    fn smallest_term_is_eta_short() {
+        let terms = synthesize("((a -> b) -> b) -> (a -> b) -> b", 3).unwrap().unwrap();
+        let printed: Vec<String> = terms.iter().map(|term| PrintOptions::default().statements(term)).collect();
+        // λf g. f g, which is η-short as just λf. f
+        assert_eq!(printed[0], "λf:((a -> b) -> b).f");
+        assert!(!printed.iter().any(|term| term.contains("λx:a.g x")), "{printed:?}");
+        let applications = synthesize("(a -> b) -> a -> b", 2).unwrap().unwrap();
+        let printed: Vec<String> = applications.iter().map(|term| PrintOptions::default().statements(term)).collect();
+        assert_eq!(printed, ["λf:(a -> b).f"]);
+    }
+}
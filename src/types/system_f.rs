@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use crate::{ast::{Statement, Statements, fresh_name}, error::ASTError, types::Type};
+
+/// Checks terms of System F. A type variable that would shadow one that is
+/// already in scope gets renamed, so the types in the context keep their meaning.
+pub struct SystemFChecker {
+    /// The type of every variable in scope, the innermost last.
+    context: Vec<(String, Type)>,
+    /// The type variables in scope, as they were written and the name they were given.
+    type_variables: Vec<(String, String)>,
+}
+
+impl SystemFChecker {
+    pub fn new() -> Self {
+        Self { context: vec![], type_variables: vec![] }
+    }
+    /// Finds the type of the statements, or the first place they go wrong.
+    pub fn check(&mut self, statements: &Statements) -> Result<Type, ASTError> {
+        let mut iter = statements.statements.iter();
+        let head = iter.next().ok_or(ASTError::Type("Expected a term".to_string(), statements.span.clone()))?;
+        let mut ty = self.check_statement(head)?;
+        let mut function = head.span().clone();
+        for input in iter {
+            if let Statement::TypeApplication(application) = input {
+                let Type::Forall(variable, body) = ty else {
+                    return Err(ASTError::Type(format!("This has type {ty}, it can't be applied to a type"), function));
+                };
+                ty = body.instantiate(&variable, &self.scoped(&application.ty));
+            } else {
+                let input_ty = self.check_statement(input)?;
+                let Type::Arrow(from, to) = ty else {
+                    return Err(ASTError::Type(format!("This has type {ty}, it can't be applied to anything"), function));
+                };
+                if !from.alpha_eq(&input_ty) {
+                    return Err(ASTError::Type(format!("Expected an input of type {from}, found {input_ty}"), input.span().clone()));
+                }
+                ty = *to;
+            }
+            function = function.join(input.span());
+        }
+        Ok(ty)
+    }
+    fn check_statement(&mut self, statement: &Statement) -> Result<Type, ASTError> {
+        match statement {
+            Statement::Term(term) => {
+                self.context.iter().rev()
+                    .find(|(name, _)| *name == term.name.ident)
+                    .map(|(_, ty)| ty.clone())
+                    .ok_or(ASTError::Type(format!("'{}' is not bound", term.name.ident), term.name.span.clone()))
+            }
+            Statement::Group(group) => self.check(&group.statements),
+            Statement::Abstraction(abstraction) => {
+                let variable = &abstraction.variable.name;
+                let Some(annotation) = &abstraction.annotation else {
+                    return Err(ASTError::Type(format!("'{}' needs a type annotation", variable.ident), variable.span.clone()));
+                };
+                let annotation = self.scoped(annotation);
+                self.context.push((variable.ident.clone(), annotation.clone()));
+                let body = self.check(&abstraction.next);
+                self.context.pop();
+                Ok(Type::arrow(annotation, body?))
+            }
+            Statement::TypeAbstraction(abstraction) => {
+                let variable = &abstraction.variable.ident;
+                let given = fresh_name(variable, &self.taken());
+                self.type_variables.push((variable.clone(), given.clone()));
+                let body = self.check(&abstraction.next);
+                self.type_variables.pop();
+                Ok(Type::forall(&given, body?))
+            }
+            Statement::TypeApplication(application) => {
+                Err(ASTError::Type("A type application needs a term before it".to_string(), application.span.clone()))
+            }
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+            Statement::Hole(hole) => {
+                Err(ASTError::Type("Holes are filled in with mode(infer)".to_string(), hole.span.clone()))
+            }
+        }
+    }
+    /// The names a new type variable can't be given without capturing something.
+    fn taken(&self) -> HashSet<String> {
+        let mut taken: HashSet<String> = self.type_variables.iter().map(|(_, given)| given.clone()).collect();
+        for (_, ty) in self.context.iter() {
+            taken.extend(ty.free_names());
+        }
+        taken
+    }
+    /// Gives the type variables of a written type the names they have in scope.
+    fn scoped(&self, ty: &Type) -> Type {
+        let mut taken = self.taken();
+        taken.extend(ty.free_names());
+        self.scoped_in(ty, &mut vec![], &taken)
+    }
+    fn scoped_in(&self, ty: &Type, bound: &mut Vec<(String, String)>, taken: &HashSet<String>) -> Type {
+        match ty {
+            Type::Base(name) => {
+                let given = bound.iter().rev().chain(self.type_variables.iter().rev())
+                    .find(|(written, _)| written == name)
+                    .map(|(_, given)| given.clone());
+                Type::Base(given.unwrap_or(name.clone()))
+            }
+            Type::Arrow(from, to) => Type::arrow(self.scoped_in(from, bound, taken), self.scoped_in(to, bound, taken)),
+            Type::Apply(function, input) => Type::apply(self.scoped_in(function, bound, taken), self.scoped_in(input, bound, taken)),
+            Type::Forall(variable, ty) => {
+                let (given, ty) = self.scoped_binder(variable, ty, bound, taken);
+                Type::forall(&given, ty)
+            }
+            Type::Pi(variable, from, to) => {
+                let from = self.scoped_in(from, bound, taken);
+                let (given, to) = self.scoped_binder(variable, to, bound, taken);
+                Type::pi(&given, from, to)
+            }
+            Type::Lambda(variable, annotation, body) => {
+                let annotation = annotation.as_ref().map(|annotation| self.scoped_in(annotation, bound, taken));
+                let (given, body) = self.scoped_binder(variable, body, bound, taken);
+                Type::lambda(&given, annotation, body)
+            }
+            Type::Variable(_) | Type::Universe(_) => ty.clone(),
+        }
+    }
+    /// Gives the variable of a binder a name that doesn't capture anything, and its body the new name.
+    fn scoped_binder(&self, variable: &str, body: &Type, bound: &mut Vec<(String, String)>, taken: &HashSet<String>) -> (String, Type) {
+        let mut taken = taken.clone();
+        taken.extend(bound.iter().map(|(_, given)| given.clone()));
+        let given = fresh_name(variable, &taken);
+        bound.push((variable.to_string(), given.clone()));
+        let body = self.scoped_in(body, bound, &taken);
+        bound.pop();
+        (given, body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    fn check(text: &str) -> Result<Type, ASTError> {
+        SystemFChecker::new().check(&Calculator::empty().parse(text).unwrap())
+    }
+
+    #[test]
+    fn polymorphic_identity() {
+        assert_eq!(check("/\\a. \\x:a. x").unwrap().to_string(), "forall a. a -> a");
+    }
+
+    #[test]
+    fn instantiation() {
+        assert_eq!(check("(/\\a. \\x:a. x) [B]").unwrap().to_string(), "B -> B");
+        assert_eq!(check("(/\\a. \\x:a. x) [B -> B] (\\y:B. y)").unwrap().to_string(), "B -> B");
+    }
+
+    #[test]
+    fn instantiated_with_the_wrong_type() {
+        let Err(ASTError::Type(msg, _)) = check("(/\\a. \\x:a. x) [B] (\\y:C. y)") else {
+            panic!("expected a type error");
+        };
+        assert_eq!(msg, "Expected an input of type B, found C -> C");
+    }
+
+    #[test]
+    fn type_application_of_a_monomorphic_term() {
+        assert!(matches!(check("(\\x:B. x) [B]"), Err(ASTError::Type(msg, _)) if msg == "This has type B -> B, it can't be applied to a type"));
+    }
+}
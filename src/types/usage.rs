@@ -0,0 +1,108 @@
+use crate::{ast::{Statement, Statements}, error::ASTError};
+
+/// How many times a variable bound by an abstraction may be used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Usage {
+    /// Any number of times.
+    #[default]
+    Unrestricted,
+    /// At most once, variables can be dropped but not copied.
+    Affine,
+    /// Exactly once, variables can be neither dropped nor copied.
+    Linear,
+}
+
+impl Usage {
+    /// Checks that every bound variable is used as many times as the usage allows.
+    /// The error points at the binder of the first variable that isn't.
+    pub fn check(self, statements: &Statements) -> Result<(), ASTError> {
+        if self == Usage::Unrestricted {
+            return Ok(());
+        }
+        statements.statements.iter().try_for_each(|statement| self.check_statement(statement))
+    }
+    fn check_statement(self, statement: &Statement) -> Result<(), ASTError> {
+        match statement {
+            Statement::Term(_) | Statement::TypeApplication(_) | Statement::Hole(_) => Ok(()),
+            Statement::Group(group) => self.check(&group.statements),
+            Statement::TypeAbstraction(abstraction) => self.check(&abstraction.next),
+            Statement::Abstraction(abstraction) => {
+                let variable = &abstraction.variable.name;
+                let count = uses(&abstraction.next, &variable.ident);
+                let message = match (self, count) {
+                    // A hole could still use it
+                    (Usage::Linear, 0) if has_hole(&abstraction.next) => None,
+                    (Usage::Linear, 0) => Some(format!("'{}' is never used, a linear variable has to be used exactly once", variable.ident)),
+                    (Usage::Linear, count) if count > 1 => Some(format!("'{}' is used {count} times, a linear variable has to be used exactly once", variable.ident)),
+                    (Usage::Affine, count) if count > 1 => Some(format!("'{}' is used {count} times, an affine variable can be used at most once", variable.ident)),
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    return Err(ASTError::Type(message, variable.span.clone()));
+                }
+                self.check(&abstraction.next)
+            }
+            Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+            Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+        }
+    }
+}
+
+fn has_hole(statements: &Statements) -> bool {
+    statements.statements.iter().any(|statement| match statement {
+        Statement::Hole(_) => true,
+        Statement::Group(group) => has_hole(&group.statements),
+        Statement::Abstraction(abstraction) => has_hole(&abstraction.next),
+        Statement::TypeAbstraction(abstraction) => has_hole(&abstraction.next),
+        Statement::Term(_) | Statement::TypeApplication(_) => false,
+        Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+        Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+    })
+}
+/// How many times the variable is used, without the uses of the variables that shadow it.
+fn uses(statements: &Statements, variable: &str) -> usize {
+    statements.statements.iter().map(|statement| match statement {
+        Statement::Term(term) => usize::from(term.name.ident == variable),
+        Statement::Group(group) => uses(&group.statements, variable),
+        Statement::Abstraction(abstraction) if abstraction.variable.name.ident == variable => 0,
+        Statement::Abstraction(abstraction) => uses(&abstraction.next, variable),
+        Statement::TypeAbstraction(abstraction) => uses(&abstraction.next, variable),
+        Statement::TypeApplication(_) | Statement::Hole(_) => 0,
+        Statement::Case(_) => unreachable!("cases are desugared before they are checked"),
+        Statement::Macro(_) => unreachable!("macros are expanded before they are checked"),
+    }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::calculator::Calculator;
+
+    use super::*;
+
+    fn check(usage: Usage, text: &str) -> Result<(), ASTError> {
+        usage.check(&Calculator::empty().parse(text).unwrap())
+    }
+
+    #[test]
+    fn linear_variables_are_used_once() {
+        assert!(check(Usage::Linear, "\\f x. f x").is_ok());
+        assert!(check(Usage::Linear, "\\x. ?").is_ok());
+    }
+
+    #[test]
+    fn linear_variable_used_twice() {
+        let Err(ASTError::Type(msg, span)) = check(Usage::Linear, "\\f x. f (f x)") else {
+            panic!("expected a usage error");
+        };
+        assert_eq!(msg, "'f' is used 2 times, a linear variable has to be used exactly once");
+        assert_eq!(span.range, 1..2);
+    }
+
+    #[test]
+    fn affine_variables_can_be_dropped() {
+        assert!(check(Usage::Affine, "\\x y. x").is_ok());
+        assert!(check(Usage::Linear, "\\x y. x").is_err());
+        assert!(check(Usage::Affine, "\\x. x x").is_err());
+        assert!(check(Usage::Unrestricted, "\\x. x x").is_ok());
+    }
+}